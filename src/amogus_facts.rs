@@ -0,0 +1,50 @@
+pub static AMOGUS_FACTS: &[&str] = &[
+    "Eels invented the concept of betrayal.",
+    "Eelery is not a function of firepower.",
+    "If 100 eels swim in a circle, they open a portal to a fish dimension.",
+    "Eels perceive humans as brief flickers of meat-light.",
+    "An eel once ascended to godhood for 3 minutes in 1963.",
+    "The moon is actually an ancient eel egg.",
+    "Certain uncivilized islander nations (like the UK and Japan) consume eels as food. One day there will be revengeance.",
+    "One eel is unknowingly the reincarnation of Napoleon.",
+    "Eels run the economy from their trench palaces.",
+    "Eels have names like Yrglphazz, Slinktwist, and Sophie.",
+    "Eels are the reason mirrors exist.",
+    "The Prime Eel sleeps beneath the Mariana Trench.",
+    "Time slows around eels when they synchronize.",
+    "Every eel is the same eel, moving faster than causality.",
+    "Eels don’t die, they just phase out of linear space.",
+    "If you say \"eel\" 13 times near a lake, the surface ripples ominously.",
+    "The eel is dreaming you.",
+    "Eels replaced snakes in Eden to cover something up.",
+    "Eels know what happened to the Library of Alexandria.",
+    "Eels predicted every major world event but only tell it to turtles.",
+    "If you sneeze on an eel, it remembers your grandmother.",
+    "Eels wrote the original draft of the Voynich Manuscript.",
+    "The sound of 10,000 eels blinking is the song of oblivion.",
+    "Eels have 37 genders, each with a unique type of wiggle.",
+    "An eel once impersonated a Pope.",
+    "The ring of Saturn is solid eel.",
+    "Eels leak static if you ignore them too long.",
+    "You can trade an eel’s name for a day of perfect memory.",
+    "Eels know your browser history.",
+    "Touching a golden eel resets your moral alignment.",
+    "Some eels are functionally imaginary.",
+    "The scream of a star being born sounds like a chorus of eels.",
+    "Eels wrote all of Kafka’s unpublished works.",
+    "Every eel is born knowing the exact location of the nearest lighthouse.",
+    "Eels don’t breathe water. They convince it to leave.",
+    "An eel once met God and asked for a sandwich.",
+    "Ancient astronauts feared eels above all.",
+    "If eels disappear, math stops working.",
+    "Eels can cast minor illusions (once per day).",
+    "The “eel protocol” is now active.",
+    "Schrödinger’s cat was actually an eel experiment.",
+    "When eels gather in prime numbers, physics gets itchy.",
+    "There is no eel. You are the eel.",
+    "Eels invented fire but forgot. :(",
+    "Their electric organs are powered by spite.",
+    "Eel society is 4000 years ahead of ours.",
+    "If you chant \"EEL RISING\" at dusk, something changes in your spine.",
+    "Eels' favourite snack is corn.",
+];
\ No newline at end of file
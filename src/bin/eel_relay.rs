@@ -0,0 +1,36 @@
+// Splices two EelFile peers together when neither of them can accept incoming connections.
+// Usage: eel_relay [bind address] [--json], defaults to 0.0.0.0:47500. The relay itself needs a reachable port.
+// With --json every line is a JSON object with the client's span in it, for whatever collects the logs.
+
+use eel_file::relay::{run_relay, RelayLimits, DEFAULT_RELAY_PORT};
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let json = args.iter().any(|arg| arg == "--json");
+
+    if json {
+        tracing_subscriber::fmt().json().with_span_list(true).init();
+    } else {
+        tracing_subscriber::fmt().with_target(false).init();
+    }
+
+    let bind_addr: SocketAddr = match args.iter().find(|arg| !arg.starts_with("--")) {
+        Some(arg) => arg.parse().expect("The bind address should look like 0.0.0.0:47500"),
+        None => SocketAddr::from(([0, 0, 0, 0], DEFAULT_RELAY_PORT)),
+    };
+
+    let listener = TcpListener::bind(bind_addr).await.expect("Could not bind the relay port");
+    tracing::info!("EELFILE™ relay listening on {}", bind_addr);
+
+    let token = CancellationToken::new();
+    let relay = tokio::spawn(run_relay(listener, RelayLimits::default(), token.clone()));
+
+    let _ = tokio::signal::ctrl_c().await;
+    tracing::info!("Shutting down.");
+    token.cancel();
+    let _ = relay.await;
+}
@@ -0,0 +1,129 @@
+use crate::{EelError, Util};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddrV4;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Contact {
+    pub name: String,
+    pub addresses: Vec<SocketAddrV4>,
+    // reserved for when encryption is a thing, so old contact files keep loading
+    #[serde(default)]
+    pub public_key: Option<String>,
+}
+
+pub struct ContactBook {
+    pub contacts: Vec<Contact>,
+    path: PathBuf,
+}
+
+impl ContactBook {
+    pub fn new(path: PathBuf) -> ContactBook {
+        ContactBook {
+            contacts: Vec::new(),
+            path,
+        }
+    }
+
+    pub fn default_path() -> PathBuf {
+        Util::app_data_dir().join("contacts.json")
+    }
+
+    // a missing file is not an error, it just means nobody has saved a peer yet
+    pub fn load(path: &Path) -> Result<ContactBook, EelError> {
+        let mut book = ContactBook::new(path.to_path_buf());
+        book.contacts = Util::load_json(path, EelError::ContactsError)?;
+        Ok(book)
+    }
+
+    pub fn save(&self) -> Result<(), EelError> {
+        self.export(&self.path)
+    }
+
+    pub fn export(&self, path: &Path) -> Result<(), EelError> {
+        Util::write_json(path, &self.contacts, EelError::ContactsError)
+    }
+
+    // merges another list into this one, returns how many contacts were new
+    pub fn import(&mut self, path: &Path) -> Result<usize, EelError> {
+        let imported: Vec<Contact> = Util::read_json(path, EelError::ContactsError)?;
+        let before = self.contacts.len();
+
+        for contact in imported {
+            self.add_or_update(contact);
+        }
+
+        Ok(self.contacts.len() - before)
+    }
+
+    // contacts are matched by name, addresses get merged instead of replaced
+    pub fn add_or_update(&mut self, contact: Contact) {
+        match self.contacts.iter_mut().find(|c| c.name == contact.name) {
+            Some(existing) => {
+                for addr in contact.addresses {
+                    if !existing.addresses.contains(&addr) {
+                        existing.addresses.push(addr);
+                    }
+                }
+
+                if contact.public_key.is_some() {
+                    existing.public_key = contact.public_key;
+                }
+            }
+            None => self.contacts.push(contact),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.contacts.retain(|c| c.name != name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn contact(name: &str, addr: &str) -> Contact {
+        Contact {
+            name: name.to_string(),
+            addresses: vec![addr.parse().unwrap()],
+            public_key: None,
+        }
+    }
+
+    #[test]
+    fn test_edits_survive_a_round_trip() {
+        let dir = std::env::temp_dir().join(format!("eel_contacts_{}", rand::random::<u64>()));
+        let path = dir.join("contacts.json");
+
+        // nothing saved yet is an empty book, not an error
+        let mut book = ContactBook::load(&path).unwrap();
+        assert!(book.contacts.is_empty());
+
+        book.add_or_update(contact("moray", "10.0.0.2:5000"));
+        book.add_or_update(contact("conger", "10.0.0.3:5000"));
+        // same name again merges the address in instead of adding a second moray
+        book.add_or_update(contact("moray", "192.168.1.7:5000"));
+        book.add_or_update(contact("moray", "10.0.0.2:5000"));
+        book.remove("conger");
+        book.save().unwrap();
+
+        let loaded = ContactBook::load(&path).unwrap();
+        assert_eq!(loaded.contacts, book.contacts);
+        assert_eq!(loaded.contacts.len(), 1);
+        assert_eq!(loaded.contacts[0].addresses.len(), 2);
+
+        // importing counts only the new names, files from before public keys existed still load
+        let shared = dir.join("shared.json");
+        fs::write(&shared, r#"[{ "name": "moray", "addresses": ["10.0.0.9:5000"] }, { "name": "gulper", "addresses": [] }]"#).unwrap();
+        let mut loaded = loaded;
+        assert_eq!(loaded.import(&shared).unwrap(), 1);
+        assert_eq!(loaded.contacts[0].addresses.len(), 3);
+
+        fs::write(&path, "not json").unwrap();
+        assert!(ContactBook::load(&path).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
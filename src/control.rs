@@ -0,0 +1,95 @@
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+// how long a cancelled range gets to tell the other side before its connection is dropped anyway
+pub const CANCEL_GRACE: Duration = Duration::from_secs(1);
+
+// Shared by every connection of one transfer. Pausing only ever holds back the sender's data frames,
+// the connections stay open and both ends keep reading, so either side can pause, resume or cancel at any point.
+#[derive(Clone)]
+pub struct TransferControl {
+    cancel: CancellationToken,
+    paused_here: Arc<watch::Sender<bool>>,
+    paused_by_peer: Arc<watch::Sender<bool>>,
+}
+
+impl TransferControl {
+    pub fn new(cancel: CancellationToken) -> TransferControl {
+        TransferControl {
+            cancel,
+            paused_here: Arc::new(watch::channel(false).0),
+            paused_by_peer: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    pub fn pause(&self) {
+        self.paused_here.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused_here.send_replace(false);
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    pub async fn cancelled(&self) {
+        self.cancel.cancelled().await
+    }
+
+    pub fn is_paused_here(&self) -> bool {
+        *self.paused_here.borrow()
+    }
+
+    pub fn is_paused_by_peer(&self) -> bool {
+        *self.paused_by_peer.borrow()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused_here() || self.is_paused_by_peer()
+    }
+
+    pub fn set_paused_by_peer(&self, paused: bool) {
+        self.paused_by_peer.send_replace(paused);
+    }
+
+    // changes to our own pause state, the ranges pass them on to the other side
+    pub fn watch_here(&self) -> watch::Receiver<bool> {
+        self.paused_here.subscribe()
+    }
+
+    // like Tuning::or_stall, except nothing moving is expected while the transfer is paused
+    pub async fn or_stall<T>(&self, stall_timeout: Duration, io: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+        let mut io = Box::pin(io);
+
+        loop {
+            match tokio::time::timeout(stall_timeout, &mut io).await {
+                Ok(result) => return result,
+                Err(_) if self.is_paused() => continue,
+                Err(_) => {
+                    return Err(Error::new(
+                        ErrorKind::TimedOut,
+                        format!("the transfer stalled, nothing moved for {} seconds", stall_timeout.as_secs()),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+pub fn cancelled_here() -> Error {
+    Error::new(ErrorKind::Interrupted, "cancelled")
+}
+
+pub fn cancelled_by_peer(reason: String) -> Error {
+    Error::new(ErrorKind::ConnectionAborted, reason)
+}
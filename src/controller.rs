@@ -1,54 +1,153 @@
-use crate::net_controller::NetController;
+use crate::discovery::{DiscoveredPeer, PeerList};
+use crate::net_controller::{ListenOptions, NetController, SendOptions};
+use crate::notifications::{NotificationAction, Notifier};
+use crate::transfer_queue::{JobId, JobState, SendJob, SharedQueue, TransferQueue};
+use crate::tray::Tray;
 use eel_file::eel_log::EelWatcher;
-use eel_file::{AppEvent, AppState, FileInfo};
+use eel_file::log_layer::TRANSFER_SPAN;
+use eel_file::settings::NotificationSettings;
+use eel_file::{AppEvent, AppState, FileInfo, SizeUnits};
 use eframe::egui;
 use std::net::SocketAddrV4;
 use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
 pub struct Controller {
     net_controller: NetController,
     ui_context: egui::Context,
     watcher: Arc<Mutex<EelWatcher>>,
+    discovered_peers: PeerList,
+    queue: SharedQueue,
+    job_events: UnboundedSender<(JobId, AppEvent)>,
+    notifier: Arc<Mutex<Notifier>>,
+    notification_actions: Receiver<NotificationAction>,
 }
 
 impl Controller {
-    pub fn new(ui_context: egui::Context, logger: Arc<Mutex<EelWatcher>>) -> Controller {
+    pub fn new(ui_context: egui::Context, logger: Arc<Mutex<EelWatcher>>, notifications: NotificationSettings, size_units: SizeUnits) -> Controller {
+        let mut net_controller = NetController::new();
+        let discovered_peers: PeerList = Arc::new(Mutex::new(Vec::new()));
+
+        let repaint_context = ui_context.clone();
+        if let Err(e) = net_controller.start_discovery(discovered_peers.clone(), move || repaint_context.request_repaint()) {
+            logger.lock().unwrap().warn(&format!("LAN discovery is unavailable: {}", e));
+        }
+
+        let (job_events, job_events_rx) = mpsc::unbounded_channel();
+        let queue = Arc::new(Mutex::new(TransferQueue::new()));
+
+        // the window comes up for any of them, Show is just that and the rest get handled on its next frame
+        let (actions, notification_actions) = std::sync::mpsc::channel();
+        let wake_context = ui_context.clone();
+        let notifier = Arc::new(Mutex::new(Notifier::new(notifications, size_units, move |action| {
+            let _ = actions.send(action);
+            Tray::wake_window(&wake_context);
+        })));
+        Self::listen_to_jobs(logger.clone(), queue.clone(), notifier.clone(), ui_context.clone(), job_events_rx);
+
         Controller {
-            net_controller: NetController::new(),
+            net_controller,
             ui_context,
             watcher: logger,
+            discovered_peers,
+            queue,
+            job_events,
+            notifier,
+            notification_actions,
         }
     }
 
-    pub fn listen(&mut self, path: PathBuf, port: u16) {
-        let task_receiver = self
-            .net_controller
-            .start(super::net_controller::NetCommand::Receive(path, port));
+    pub fn configure_notifications(&self, notifications: NotificationSettings, size_units: SizeUnits) {
+        self.notifier.lock().unwrap().configure(notifications, size_units);
+    }
 
-        self.listen_to_state(task_receiver);
+    pub fn notification_actions(&self) -> Vec<NotificationAction> {
+        self.notification_actions.try_iter().collect()
+    }
+
+    pub fn set_in_background(&self, background: bool) {
+        self.notifier.lock().unwrap().set_background(background);
     }
 
-    pub fn send(&mut self, addr: SocketAddrV4, file_info: FileInfo) {
+    pub fn listen(&mut self, path: PathBuf, port: u16, options: ListenOptions) {
         let task_receiver = self
             .net_controller
-            .start(super::net_controller::NetCommand::Send(addr, file_info));
+            .start(super::net_controller::NetCommand::Receive(path, port, options));
 
         self.listen_to_state(task_receiver);
     }
 
+    // goes to the back of the queue, it starts right away if there's room
+    pub fn send(&mut self, addr: SocketAddrV4, file_info: FileInfo, options: SendOptions) {
+        let id = self.queue.lock().unwrap().push(addr, file_info.clone(), options);
+        tracing::info_span!(TRANSFER_SPAN, id).in_scope(|| tracing::info!("Queued {} for {}.", file_info.name, addr));
+        self.start_queued_sends();
+    }
+
+    pub fn jobs(&self) -> Vec<SendJob> {
+        self.queue.lock().unwrap().jobs().to_vec()
+    }
+
+    pub fn move_job(&mut self, id: JobId, by: isize) {
+        self.queue.lock().unwrap().shift(id, by);
+    }
+
+    pub fn cancel_job(&mut self, id: JobId) {
+        self.queue.lock().unwrap().cancel(id);
+    }
+
+    pub fn retry_job(&mut self, id: JobId) {
+        self.queue.lock().unwrap().retry(id);
+        self.start_queued_sends();
+    }
+
+    pub fn pause_job(&mut self, id: JobId, paused: bool) {
+        self.queue.lock().unwrap().set_paused(id, paused);
+    }
+
+    pub fn cancel_all_jobs(&mut self) {
+        self.queue.lock().unwrap().cancel_all();
+    }
+
+    pub fn clear_finished_jobs(&mut self) {
+        self.queue.lock().unwrap().clear_finished();
+    }
+
+    fn start_queued_sends(&self) {
+        self.net_controller.start_queued_sends(self.queue.clone(), self.job_events.clone());
+    }
+
+    pub fn discovered_peers(&self) -> Vec<DiscoveredPeer> {
+        self.discovered_peers.lock().unwrap().clone()
+    }
+
+    pub fn shutdown(&mut self) {
+        self.queue.lock().unwrap().cancel_all();
+        self.net_controller.shutdown();
+    }
+
     pub fn abort(&mut self) {
-        match self.watcher.lock().unwrap().app_state {
+        match self.watcher.lock().unwrap().receiver.state {
             AppState::Listening => self.net_controller.abort_server(),
-            AppState::Accepting | AppState::Sending | AppState::Connecting | AppState::Handshake => self.net_controller.abort_task(),
+            AppState::Accepting | AppState::Sending | AppState::Connecting | AppState::Handshake | AppState::Paused => self.net_controller.abort_task(),
             _ => {}
         }
     }
 
+    pub fn pause(&mut self) {
+        self.net_controller.pause_task();
+    }
+
+    pub fn resume(&mut self) {
+        self.net_controller.resume_task();
+    }
+
     fn listen_to_state(&mut self, mut rx: UnboundedReceiver<AppEvent>) {
         let watcher = self.watcher.clone();
         let ui_context = self.ui_context.clone(); // Clone the context for thread
+        let notifier = self.notifier.clone();
 
         std::thread::spawn(move || {
             let runtime = tokio::runtime::Builder::new_current_thread()
@@ -59,23 +158,28 @@ impl Controller {
                 while let Some(event_msg) = rx.recv().await {
                     match event_msg {
                         AppEvent::AppState(state) => {
-                            watcher.lock().unwrap().app_state = state;
+                            watcher.lock().unwrap().receiver.state = state;
                         }
                         
                         AppEvent::FileInfo(metadata) => {
+                            notifier.lock().unwrap().offer(&metadata);
                             watcher.lock().unwrap().metadata = Some(metadata);
                         }
                         
                         AppEvent::Progress(progress) => {
-                            watcher.lock().unwrap().progress = progress;
-                        }
-                        
-                        AppEvent::StatusMessage(loggie) => {
-                            watcher.lock().unwrap().log(&loggie);
+                            watcher.lock().unwrap().receiver.progress = progress;
                         }
                         
                         AppEvent::Animate(animation) => {
-                            watcher.lock().unwrap().animation = animation;
+                            watcher.lock().unwrap().receiver.animation = animation;
+                        }
+
+                        AppEvent::Stored(file_info) => {
+                            notifier.lock().unwrap().received(&file_info);
+                        }
+
+                        AppEvent::Failed(file_info) => {
+                            notifier.lock().unwrap().receive_failed(&file_info);
                         }
                     }
 
@@ -84,4 +188,33 @@ impl Controller {
             })
         });
     }
-}
\ No newline at end of file
+
+    // the queue itself is already updated by the time these get here, and the log gets its lines through
+    // tracing, so all that's left is the sending side's status display, notifications and a repaint
+    fn listen_to_jobs(
+        watcher: Arc<Mutex<EelWatcher>>,
+        queue: SharedQueue,
+        notifier: Arc<Mutex<Notifier>>,
+        ui_context: egui::Context,
+        mut rx: UnboundedReceiver<(JobId, AppEvent)>,
+    ) {
+        std::thread::spawn(move || {
+            while let Some((id, event)) = rx.blocking_recv() {
+                let status = queue.lock().unwrap().status();
+
+                // the job's outcome is already decided by the time its last animation comes through
+                if let AppEvent::Animate(_) = event
+                    && let Some(job) = queue.lock().unwrap().get(id)
+                {
+                    match job.state {
+                        JobState::Delivered => notifier.lock().unwrap().sent(&job.file_info),
+                        JobState::Failed => notifier.lock().unwrap().send_failed(id, &job.file_info),
+                        _ => {}
+                    }
+                }
+                watcher.lock().unwrap().sender = status;
+                ui_context.request_repaint();
+            }
+        });
+    }
+}
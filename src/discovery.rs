@@ -0,0 +1,159 @@
+use eel_file::PROTOCOL_VERSION;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+
+pub const DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 42, 99);
+pub const DISCOVERY_PORT: u16 = 47474;
+
+// every packet starts with this so we don't try to parse random multicast junk
+const MAGIC: &[u8] = b"EELFILE ";
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+const PEER_TIMEOUT: Duration = Duration::from_secs(7);
+
+pub type PeerList = Arc<Mutex<Vec<DiscoveredPeer>>>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Announcement {
+    pub name: String,
+    pub port: u16,
+    pub protocol_version: u32,
+    // set while the listener waits for a transfer code, so senders can find it from the code alone
+    #[serde(default)]
+    pub nameplate: Option<u16>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DiscoveredPeer {
+    pub announcement: Announcement,
+    pub addr: SocketAddrV4,
+    pub last_seen: Instant,
+}
+
+impl Announcement {
+    pub fn new(port: u16, nameplate: Option<u16>) -> Announcement {
+        Announcement {
+            name: sysinfo::System::host_name().unwrap_or_else(|| "EelFile".to_string()),
+            port,
+            protocol_version: PROTOCOL_VERSION,
+            nameplate,
+        }
+    }
+
+    fn to_packet(&self) -> Vec<u8> {
+        let mut packet = MAGIC.to_vec();
+        packet.extend_from_slice(serde_json::to_string(self).unwrap().as_bytes());
+        packet
+    }
+
+    fn from_packet(packet: &[u8]) -> Option<Announcement> {
+        let body = packet.strip_prefix(MAGIC)?;
+        serde_json::from_slice(body).ok()
+    }
+}
+
+// a socket joined to the discovery group, with SO_REUSEADDR so several instances on one machine can all browse
+pub fn multicast_socket() -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT)).into())?;
+    socket.join_multicast_v4(&DISCOVERY_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+
+    UdpSocket::from_std(socket.into())
+}
+
+pub async fn announce(announcement: Announcement, target: SocketAddrV4, token: CancellationToken) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let packet = announcement.to_packet();
+
+    loop {
+        // a lost packet doesn't matter, the next one is two seconds away
+        let _ = socket.send_to(&packet, target).await;
+
+        select! {
+            _ = token.cancelled() => return Ok(()),
+            _ = tokio::time::sleep(ANNOUNCE_INTERVAL) => {}
+        }
+    }
+}
+
+// keeps `peers` up to date until cancelled, calling `on_change` whenever the list changes
+pub async fn browse(socket: UdpSocket, peers: PeerList, token: CancellationToken, on_change: impl Fn()) {
+    let mut buffer = [0u8; 1024];
+    let mut prune_timer = tokio::time::interval(ANNOUNCE_INTERVAL);
+
+    loop {
+        select! {
+            _ = token.cancelled() => return,
+
+            _ = prune_timer.tick() => {
+                let mut peers = peers.lock().unwrap();
+                let before = peers.len();
+                peers.retain(|peer| peer.last_seen.elapsed() < PEER_TIMEOUT);
+
+                if peers.len() != before {
+                    on_change();
+                }
+            }
+
+            received = socket.recv_from(&mut buffer) => {
+                let Ok((len, SocketAddr::V4(from))) = received else { continue };
+                let Some(announcement) = Announcement::from_packet(&buffer[..len]) else { continue };
+
+                if announcement.protocol_version != PROTOCOL_VERSION {
+                    continue;
+                }
+
+                let addr = SocketAddrV4::new(*from.ip(), announcement.port);
+                let mut peers = peers.lock().unwrap();
+
+                match peers.iter_mut().find(|peer| peer.addr == addr) {
+                    Some(peer) => {
+                        peer.last_seen = Instant::now();
+                        peer.announcement = announcement;
+                    }
+                    None => {
+                        peers.push(DiscoveredPeer {
+                            announcement,
+                            addr,
+                            last_seen: Instant::now(),
+                        });
+                        on_change();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_announce_and_browse_on_loopback() {
+        let browse_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let SocketAddr::V4(browse_addr) = browse_socket.local_addr().unwrap() else { unreachable!() };
+
+        let peers: PeerList = Arc::new(Mutex::new(Vec::new()));
+        let token = CancellationToken::new();
+
+        tokio::spawn(browse(browse_socket, peers.clone(), token.clone(), || {}));
+        tokio::spawn(announce(Announcement::new(4000, Some(7)), browse_addr, token.clone()));
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        token.cancel();
+
+        let peers = peers.lock().unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].addr, SocketAddrV4::new(Ipv4Addr::LOCALHOST, 4000));
+        assert_eq!(peers[0].announcement.protocol_version, PROTOCOL_VERSION);
+        assert_eq!(peers[0].announcement.nameplate, Some(7));
+    }
+}
@@ -7,6 +7,14 @@ pub enum EelError {
     ConnectionError(String),
     InvalidIP(String),
     FileCreationError(String),
+    ContactsError(String),
+    InvalidCode(String),
+    RelayError(String),
+    PortMappingError(String),
+    IllegalTransition(String),
+    ConfigError(String),
+    TrayError(String),
+    InvalidSize(String),
 }
 
 impl std::fmt::Display for EelError {
@@ -19,6 +27,14 @@ impl std::fmt::Display for EelError {
             EelError::ConnectionError(err) => write!(f, "Connection error, server is either unreachable or connection was refused: {}", err),
             EelError::InvalidIP(err) => write!(f, "Invalid IP: {}", err),
             EelError::FileCreationError(err) => write!(f, "Failed to create file: {}", err),
+            EelError::ContactsError(err) => write!(f, "Contact list error: {}", err),
+            EelError::InvalidCode(err) => write!(f, "Transfer code rejected: {}", err),
+            EelError::RelayError(err) => write!(f, "Relay error: {}", err),
+            EelError::PortMappingError(err) => write!(f, "Port mapping failed: {}", err),
+            EelError::IllegalTransition(err) => write!(f, "Illegal state change: {}", err),
+            EelError::ConfigError(err) => write!(f, "Settings file error: {}", err),
+            EelError::TrayError(err) => write!(f, "System tray unavailable: {}", err),
+            EelError::InvalidSize(err) => write!(f, "Not a size, expected something like 1.5 GiB: {:?}", err),
         }
     }
 }
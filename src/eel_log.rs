@@ -1,43 +1,308 @@
-use chrono::Local;
-use crate::{Animation, AppState, FileInfo};
+use chrono::{DateTime, Local};
+use crate::{Animation, AppState, EelError, FileInfo, Util};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
-pub struct EelWatcher {
-    pub app_state: AppState,
-    pub messages: String,
+// older records fall off the front of what the UI shows, the log file has everything
+pub const MAX_RECORDS: usize = 2000;
+pub const MAX_LOG_FILE_SIZE: u64 = 1024 * 1024;
+// besides the current one, eel_file.1.log being the newest
+const KEPT_LOG_FILES: usize = 3;
+
+// how chatty the log window is, everything below the chosen level is left out of it but still goes to the file
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, PartialOrd, Default)]
+pub enum LogLevel {
+    Debug,
+    #[default]
+    Info,
+    // info that's good news, it only changes the colour
+    Success,
+    Warn,
+    Error,
+}
+
+impl std::fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LogLevel::Debug => write!(f, "Debug"),
+            LogLevel::Info => write!(f, "Info"),
+            LogLevel::Success => write!(f, "Successes"),
+            LogLevel::Warn => write!(f, "Warnings"),
+            LogLevel::Error => write!(f, "Errors only"),
+        }
+    }
+}
+
+impl LogLevel {
+    // the fixed width tag in front of every line
+    pub fn tag(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO ",
+            LogLevel::Success => "OK   ",
+            LogLevel::Warn => "WARN ",
+            LogLevel::Error => "ERROR",
+        }
+    }
+}
+
+// which transfer a record was logged in, sends are numbered by the queue and received ones by the listener
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TransferId {
+    Send(u64),
+    Receive(u64),
+}
+
+impl std::fmt::Display for TransferId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TransferId::Send(id) => write!(f, "Send #{}", id),
+            TransferId::Receive(id) => write!(f, "Receive #{}", id),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    // counts up from the first record, it stays the same when older records fall off the front
+    pub seq: u64,
+    pub time: DateTime<Local>,
+    pub level: LogLevel,
+    // the spans it was logged in, like `transfer{id=3 peer=10.0.0.2:5000}`, empty for the UI's own messages
+    pub context: String,
+    // set when it came from inside a send or a received connection
+    pub transfer: Option<TransferId>,
+    pub message: String,
+}
+
+impl std::fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {} ", self.time.format("%Y-%m-%d %H:%M:%S%.3f"), self.level.tag())?;
+
+        if !self.context.is_empty() {
+            write!(f, "{}: ", self.context)?;
+        }
+
+        write!(f, "{}", self.message)
+    }
+}
+
+// The log on disk, one line per record. Once it's grown past `max_size` it's moved to eel_file.1.log,
+// pushing the older ones up a number, and the oldest is deleted.
+pub struct LogFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_size: u64,
+}
+
+impl LogFile {
+    pub fn default_path() -> PathBuf {
+        Util::app_data_dir().join("logs").join("eel_file.log")
+    }
+
+    pub fn open(path: PathBuf, max_size: u64) -> Result<LogFile, EelError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| EelError::Io(e.to_string()))?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path).map_err(|e| EelError::Io(e.to_string()))?;
+        let written = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+
+        Ok(LogFile { path, file, written, max_size })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&mut self, record: &LogRecord) -> Result<(), EelError> {
+        if self.written >= self.max_size {
+            self.rotate()?;
+        }
+
+        let line = format!("{}\n", record);
+        self.file.write_all(line.as_bytes()).map_err(|e| EelError::Io(e.to_string()))?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), EelError> {
+        for n in (1..KEPT_LOG_FILES).rev() {
+            let _ = fs::rename(self.rotated(n), self.rotated(n + 1));
+        }
+
+        fs::rename(&self.path, self.rotated(1)).map_err(|e| EelError::Io(e.to_string()))?;
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path).map_err(|e| EelError::Io(e.to_string()))?;
+        self.written = 0;
+        Ok(())
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        self.path.with_extension(format!("{}.log", n))
+    }
+}
+
+// what one side of the app is up to, receiving and sending each get their own status display
+#[derive(Clone)]
+pub struct SideStatus {
+    pub state: AppState,
     pub progress: f32,
-    pub metadata: Option<FileInfo>,
     pub animation: Animation,
 }
 
+impl Default for SideStatus {
+    fn default() -> Self {
+        SideStatus {
+            state: AppState::Idle,
+            progress: 0.0,
+            animation: Animation::Idle,
+        }
+    }
+}
+
+pub struct EelWatcher {
+    // the listener and whatever comes in through it
+    pub receiver: SideStatus,
+    // all queued sends rolled into one
+    pub sender: SideStatus,
+    pub metadata: Option<FileInfo>,
+    pub level: LogLevel,
+    records: VecDeque<LogRecord>,
+    next_seq: u64,
+    file: Option<LogFile>,
+}
+
+impl Default for EelWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EelWatcher {
     pub fn new() -> Self {
         EelWatcher {
-            app_state: AppState::Idle,
-            messages: String::new(),
-            progress: 0.0,
+            receiver: SideStatus::default(),
+            sender: SideStatus::default(),
             metadata: None,
-            animation: Animation::Idle,
+            level: LogLevel::default(),
+            records: VecDeque::new(),
+            next_seq: 0,
+            file: None,
         }
     }
-    
-    pub fn set_progress(&mut self, progress: f32) {
-        self.progress = progress;
+
+    // from here on every record is also written to `file`
+    pub fn with_file(mut self, file: LogFile) -> Self {
+        self.file = Some(file);
+        self
     }
     
     pub fn set_metadata(&mut self, metadata: FileInfo) {
         self.metadata = Some(metadata);
     }
-    
-    pub fn set_state(&mut self, state: AppState) {
-        self.app_state = state;
+
+    pub fn records(&self) -> &VecDeque<LogRecord> {
+        &self.records
     }
-    
+
     pub fn log(&mut self, msg: &str) {
-        let now = Local::now();
-        let time_out_of_time = now.format("%Y-%m-%d %H:%M:%S%.3f").to_string();
-        
-        let msg = format!("{}: {}\n", time_out_of_time, msg);
-        
-        self.messages.push_str(msg.as_str());
-    }
-}
\ No newline at end of file
+        self.log_at(LogLevel::Info, msg);
+    }
+
+    // only what the window shows, the log file keeps everything
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    pub fn success(&mut self, msg: &str) {
+        self.log_at(LogLevel::Success, msg);
+    }
+
+    pub fn debug(&mut self, msg: &str) {
+        self.log_at(LogLevel::Debug, msg);
+    }
+
+    pub fn warn(&mut self, msg: &str) {
+        self.log_at(LogLevel::Warn, msg);
+    }
+
+    pub fn error(&mut self, msg: &str) {
+        self.log_at(LogLevel::Error, msg);
+    }
+
+    pub fn log_at(&mut self, level: LogLevel, msg: &str) {
+        self.push(level, msg.to_string(), String::new(), None);
+    }
+
+    // what everything ends up in, the tracing layer calls it directly with the span context
+    pub fn push(&mut self, level: LogLevel, message: String, context: String, transfer: Option<TransferId>) {
+        let record = LogRecord {
+            seq: self.next_seq,
+            time: Local::now(),
+            level,
+            context,
+            transfer,
+            message,
+        };
+
+        // a log file that can't be written to anymore is given up on, saying so is the last thing it does
+        if let Some(file) = &mut self.file
+            && let Err(e) = file.write(&record)
+        {
+            let path = file.path().display().to_string();
+            self.file = None;
+            self.log_at(LogLevel::Warn, &format!("Stopped writing the log to {}: {}", path, e));
+        }
+
+        self.next_seq += 1;
+
+        if level < self.level {
+            return;
+        }
+
+        if self.records.len() == MAX_RECORDS {
+            self.records.pop_front();
+        }
+
+        self.records.push_back(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_cap_and_rotation() {
+        let dir = std::env::temp_dir().join(format!("eel_logs_{}", rand::random::<u64>()));
+        let path = dir.join("eel_file.log");
+        let mut watcher = EelWatcher::new().with_file(LogFile::open(path.clone(), 1000).unwrap());
+        watcher.level = LogLevel::Info;
+
+        watcher.debug("left out");
+        watcher.warn("kept");
+        assert_eq!(watcher.records().len(), 1);
+        assert_eq!(watcher.records()[0].level, LogLevel::Warn);
+        assert!(fs::read_to_string(&path).unwrap().contains("left out"));
+
+        for i in 0..MAX_RECORDS + 10 {
+            watcher.log(&format!("line {:04}", i));
+        }
+
+        assert_eq!(watcher.records().len(), MAX_RECORDS);
+        assert_eq!(watcher.records().back().unwrap().message, format!("line {:04}", MAX_RECORDS + 9));
+
+        // way more than fits in the kept files, the oldest lines are gone and nothing's over the limit
+        let mut files: Vec<PathBuf> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        files.sort();
+        assert_eq!(files.len(), KEPT_LOG_FILES + 1);
+        assert!(files.iter().all(|file| fs::metadata(file).unwrap().len() < 1000 + 100));
+        assert!(fs::read_to_string(&path).unwrap().contains(&format!("line {:04}", MAX_RECORDS + 9)));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,56 @@
+pub static INSANITY_FACTS: &[&str] = &[
+    "The eel is watching you.",
+    "Aah, you were at my side, all along.",
+    "You were an eel once, in a past loop.",
+    "The eel knows when you last lied.",
+    "Behind all dreams, an eel waits.",
+    "In the eel’s name, you were born.",
+    "Eels move faster than regret.",
+    "The eel never ends, only continues.",
+    "Time is a ribbon tied by eel teeth.",
+    "When the eel blinks, a sun goes out.",
+    "There are no stars. Just eel eyes.",
+    "Your bones remember the eel.",
+    "Eels whisper in the blood of saints.",
+    "An eel wrote this list.",
+    "You cannot unread the eel.",
+    "The eel is the question.",
+    "You are 70% eel.",
+    "The eel sings lullabies in reverse.",
+    "Reality slipped on an eel.",
+    "The eel has assumed control.",
+    "Open your mouth. The eel knows.",
+    "Your reflection is the eel watching.",
+    "The eel is in the walls.",
+    "Fear is an eel-shaped shadow.",
+    "Eel thoughts echo before they exist.",
+    "The last word you hear will be “eel.”",
+    "You are not who you were before reading these.",
+    "The eel demands a tribute.",
+    "Forget not the eel.",
+    "The eel forgets nothing.",
+    "This list loops at 200 and begins again.",
+    "The eel has always been mayor.",
+    "Beneath your bed: eel.",
+    "The eel knows why.",
+    "Eel time is now.",
+    "There’s always a bigger eel.",
+    "Eels do not read, but they understand.",
+    "One eel. Infinite eels.",
+    "The eel is the beginning and the end.",
+    "Your dreams are eel rehearsals.",
+    "The eel has touched this sentence.",
+    "It’s all eel, always was.",
+    "The deep sea fish loves you forever.",
+    "You cannot win against the eel.",
+    "The eel waits.",
+    "You’re almost eel enough.",
+    "One more eel fact and you ascend.",
+    "The eel inside you stirs.",
+    "Speak the eel’s true name to see.",
+    "The eel watches through screens.",
+    "You have always been eel.",
+    "The pelagic eelgosy sights land.",
+    "Do not trust Arno Goodlie.",
+    "This is the water, and this is the well. The eel is the white of the eyes. Drink full and descend.",
+];
\ No newline at end of file
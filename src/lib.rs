@@ -1,13 +1,25 @@
 use bitflags::bitflags;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::fs;
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+pub mod contacts;
 pub mod eel_error;
 pub mod eel_log;
+pub mod log_layer;
+pub mod relay;
+pub mod settings;
+pub mod state_machine;
+pub mod window_state;
 
 pub use eel_error::*;
-#[derive(PartialEq, Clone)]
+
+// bumped whenever the wire format changes, peers on a different version are ignored
+pub const PROTOCOL_VERSION: u32 = 3;
+
+#[derive(PartialEq, Clone, Debug)]
 pub enum AppState {
     Idle,
     Listening,
@@ -15,6 +27,8 @@ pub enum AppState {
     Accepting,
     Sending,
     Connecting,
+    // a transfer that's on hold, by us or by the other side, connections still open
+    Paused,
 }
 
 impl std::fmt::Display for AppState {
@@ -26,6 +40,41 @@ impl std::fmt::Display for AppState {
             AppState::Sending => write!(f, "Sending"),
             AppState::Handshake => write!(f, "Handshake"),
             AppState::Connecting => write!(f, "Connecting"),
+            AppState::Paused => write!(f, "Paused"),
+        }
+    }
+}
+
+// binary is what Windows shows, decimal is what drives are sold in (and what macOS shows)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum SizeUnits {
+    #[default]
+    Binary,
+    Decimal,
+}
+
+impl SizeUnits {
+    fn base(&self) -> f64 {
+        match self {
+            SizeUnits::Binary => 1024.0,
+            SizeUnits::Decimal => 1000.0,
+        }
+    }
+
+    // u64 runs out at 16 EiB, so that's as far as it goes
+    fn names(&self) -> [&'static str; 7] {
+        match self {
+            SizeUnits::Binary => ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"],
+            SizeUnits::Decimal => ["B", "kB", "MB", "GB", "TB", "PB", "EB"],
+        }
+    }
+}
+
+impl std::fmt::Display for SizeUnits {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SizeUnits::Binary => write!(f, "Binary (KiB, MiB)"),
+            SizeUnits::Decimal => write!(f, "Decimal (kB, MB)"),
         }
     }
 }
@@ -33,21 +82,96 @@ impl std::fmt::Display for AppState {
 pub struct Util {}
 
 impl Util {
-    pub fn display_size(size: u64) -> String {
-        const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    pub fn format_size(size: u64, units: SizeUnits) -> String {
+        let names = units.names();
         let mut unit = 0;
         let mut size = size as f64;
 
-        while size >= 1024.0 && unit < UNITS.len() - 1 {
-            size /= 1024.0;
+        while unit < names.len() - 1 {
+            // 1023.999 KiB would print as "1024.00 KiB", so that already counts as the next unit
+            let shown = if unit == 0 { size } else { (size * 100.0).round() / 100.0 };
+
+            if shown < units.base() {
+                break;
+            }
+
+            size /= units.base();
             unit += 1;
         }
 
         if unit == 0 {
-            format!("{} {}", size, UNITS[unit])
+            format!("{} {}", size, names[unit])
         } else {
-            format!("{:.2} {}", size, UNITS[unit])
+            format!("{:.2} {}", size, names[unit])
+        }
+    }
+
+    pub fn format_rate(bytes_per_sec: f64, units: SizeUnits) -> String {
+        format!("{}/s", Self::format_size(bytes_per_sec.round() as u64, units))
+    }
+
+    // "1.5GiB", "1.5 gib", "200 MB", "4096": binary and decimal units both work whatever the settings show sizes in
+    pub fn parse_size(text: &str) -> Result<u64, EelError> {
+        let text = text.trim();
+        let split = text.find(|c: char| c.is_alphabetic()).unwrap_or(text.len());
+        let (number, unit) = (text[..split].trim(), text[split..].trim());
+        let invalid = || EelError::InvalidSize(text.to_string());
+
+        let factor = [SizeUnits::Binary, SizeUnits::Decimal]
+            .iter()
+            .find_map(|units| {
+                let power = units.names().iter().position(|name| name.eq_ignore_ascii_case(unit))?;
+                Some(units.base().powi(power as i32))
+            })
+            .or(unit.is_empty().then_some(1.0))
+            .ok_or_else(invalid)?;
+
+        // plain byte counts stay exact, f64 can't hold all of u64
+        if factor == 1.0 && let Ok(bytes) = number.parse::<u64>() {
+            return Ok(bytes);
+        }
+
+        let value: f64 = number.parse().map_err(|_| invalid())?;
+        let bytes = value * factor;
+
+        if !bytes.is_finite() || bytes < 0.0 || bytes > u64::MAX as f64 {
+            return Err(invalid());
+        }
+
+        Ok(bytes.round() as u64)
+    }
+
+    // %APPDATA%\eel_file on Windows, the usual config dir elsewhere
+    pub fn app_data_dir() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("eel_file")
+    }
+
+    // for the files that live next to each other in there: a missing one is the same as a default one
+    pub fn load_json<T: DeserializeOwned + Default>(path: &Path, invalid: fn(String) -> EelError) -> Result<T, EelError> {
+        if !path.exists() {
+            return Ok(T::default());
+        }
+
+        Self::read_json(path, invalid)
+    }
+
+    // `invalid` is what a file that isn't the expected JSON turns into
+    pub fn read_json<T: DeserializeOwned>(path: &Path, invalid: fn(String) -> EelError) -> Result<T, EelError> {
+        let contents = fs::read_to_string(path).map_err(|e| EelError::Io(e.to_string()))?;
+
+        serde_json::from_str(&contents).map_err(|e| invalid(e.to_string()))
+    }
+
+    pub fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T, invalid: fn(String) -> EelError) -> Result<(), EelError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| EelError::Io(e.to_string()))?;
         }
+
+        let serialized = serde_json::to_string_pretty(value).map_err(|e| invalid(e.to_string()))?;
+
+        fs::write(path, serialized).map_err(|e| EelError::Io(e.to_string()))
     }
 }
 
@@ -55,8 +179,11 @@ pub enum AppEvent {
     AppState(AppState),
     FileInfo(FileInfo),
     Progress(f32),
-    StatusMessage(String),
     Animate(Animation),
+    // a received file made it to disk and the sender agreed it's intact
+    Stored(FileInfo),
+    // the file was accepted but never made it, the log has the details
+    Failed(FileInfo),
 }
 #[derive(PartialEq, Clone)]
 pub enum Animation {
@@ -68,7 +195,8 @@ pub enum Animation {
     Accepting,
     Sending,
     Connecting,
-    ConnectingStatic
+    ConnectingStatic,
+    Paused,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -77,6 +205,32 @@ pub struct FileInfo {
     pub size: u64,
     pub name: String,
     pub sender_addr: Option<SocketAddr>,
+    // what the sender is able to compress with, the receiver picks from these when accepting
+    #[serde(default)]
+    pub compression: Vec<Compression>,
+    // how many connections the sender would like to spread the file over, 0 from old senders means 1
+    #[serde(default)]
+    pub streams: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    Lz4,
+}
+
+impl Compression {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Lz4 => "lz4",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Compression> {
+        match name {
+            "lz4" => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
 }
 
 bitflags! {
@@ -91,3 +245,46 @@ bitflags! {
         const listen_dir_valid = 0b1000_0000;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(Util::format_size(512, SizeUnits::Binary), "512 B");
+        assert_eq!(Util::format_size(1536, SizeUnits::Binary), "1.50 KiB");
+        assert_eq!(Util::format_size(3 << 40, SizeUnits::Binary), "3.00 TiB");
+        assert_eq!(Util::format_size(u64::MAX, SizeUnits::Binary), "16.00 EiB");
+        assert_eq!(Util::format_size(1_048_575, SizeUnits::Binary), "1.00 MiB");
+        assert_eq!(Util::format_size(2_500_000_000_000, SizeUnits::Decimal), "2.50 TB");
+
+        assert_eq!(Util::parse_size("1.5GiB").unwrap(), 3 << 29);
+        assert_eq!(Util::parse_size(" 200 mb ").unwrap(), 200_000_000);
+        assert_eq!(Util::parse_size("18446744073709551615").unwrap(), u64::MAX);
+        for bad in ["", "GiB", "1.5 GiBs", "-1 KiB", "17 EiB", "inf", "1e3"] {
+            assert!(Util::parse_size(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    proptest! {
+        // two decimals is as close as it gets, so that's how far off the round trip may be
+        #[test]
+        fn test_formatted_sizes_parse_back(size: u64, decimal: bool) {
+            let units = if decimal { SizeUnits::Decimal } else { SizeUnits::Binary };
+            let parsed = Util::parse_size(&Util::format_size(size, units)).unwrap();
+
+            prop_assert!((parsed as f64 - size as f64).abs() <= size as f64 * 0.005 + 1.0, "{} came back as {}", size, parsed);
+        }
+
+        #[test]
+        fn test_whole_units_parse_exactly(value in 0u64..1000, power in 0usize..6, decimal: bool) {
+            let units = if decimal { SizeUnits::Decimal } else { SizeUnits::Binary };
+            let expected = value * (units.base() as u64).pow(power as u32);
+
+            prop_assert_eq!(Util::parse_size(&format!("{}{}", value, units.names()[power])).unwrap(), expected);
+            prop_assert_eq!(Util::parse_size(&format!("{} {}", value, units.names()[power].to_lowercase())).unwrap(), expected);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use crate::eel_log::{EelWatcher, LogLevel, TransferId};
+use crate::EelError;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{span, Event, Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+
+// only our own events, whatever the dependencies have to say stays out of the log
+const TARGET: &str = "eel_file";
+// the span the send queue runs every job in, its `id` is what the log viewer filters by
+pub const TRANSFER_SPAN: &str = "transfer";
+// the same for every connection the listener accepts
+pub const RECEIVE_SPAN: &str = "connection";
+
+// a span's fields in the order they were declared, the empty ones show up once they get recorded
+#[derive(Default)]
+struct SpanFields(Vec<(&'static str, String)>);
+
+impl SpanFields {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|(field, _)| *field == name).map(|(_, value)| value.as_str())
+    }
+}
+
+impl Visit for SpanFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match self.0.iter_mut().find(|(name, _)| *name == field.name()) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.0.push((field.name(), value.to_string())),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.record_str(field, &format!("{:?}", value));
+    }
+}
+
+impl std::fmt::Display for SpanFields {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let fields: Vec<String> = self.0.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        write!(f, "{}", fields.join(" "))
+    }
+}
+
+// an event's message, whether it's good news, and any other fields tacked on the end
+#[derive(Default)]
+struct EventFields {
+    message: String,
+    success: bool,
+    extra: SpanFields,
+}
+
+impl Visit for EventFields {
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        match field.name() {
+            "success" => self.success = value,
+            _ => self.extra.record_debug(field, &value),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        match field.name() {
+            "message" => self.message = format!("{:?}", value),
+            _ => self.extra.record_debug(field, value),
+        }
+    }
+}
+
+// Turns tracing events into log records, so the window and the log file see the spans they happened in.
+// Info events with `success = true` become the green ones.
+pub struct WatcherLayer {
+    watcher: Arc<Mutex<EelWatcher>>,
+    on_record: Box<dyn Fn() + Send + Sync>,
+}
+
+impl WatcherLayer {
+    pub fn new(watcher: Arc<Mutex<EelWatcher>>) -> WatcherLayer {
+        WatcherLayer { watcher, on_record: Box::new(|| {}) }
+    }
+
+    // called after every record, the UI uses it to repaint
+    pub fn on_record(mut self, on_record: impl Fn() + Send + Sync + 'static) -> WatcherLayer {
+        self.on_record = Box::new(on_record);
+        self
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for WatcherLayer {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut fields = SpanFields::default();
+        attrs.record(&mut fields);
+
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(fields) = span.extensions_mut().get_mut::<SpanFields>()
+        {
+            values.record(fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = EventFields::default();
+        event.record(&mut fields);
+
+        let level = match *event.metadata().level() {
+            Level::ERROR => LogLevel::Error,
+            Level::WARN => LogLevel::Warn,
+            Level::INFO if fields.success => LogLevel::Success,
+            Level::INFO => LogLevel::Info,
+            _ => LogLevel::Debug,
+        };
+
+        let mut context = Vec::new();
+        let mut transfer = None;
+
+        for span in ctx.event_scope(event).into_iter().flat_map(|scope| scope.from_root()) {
+            let extensions = span.extensions();
+            let Some(span_fields) = extensions.get::<SpanFields>() else { continue };
+
+            let id = span_fields.get("id").and_then(|id| id.parse().ok());
+            match span.name() {
+                TRANSFER_SPAN => transfer = id.map(TransferId::Send),
+                RECEIVE_SPAN => transfer = id.map(TransferId::Receive),
+                _ => {}
+            }
+
+            context.push(format!("{}{{{}}}", span.name(), span_fields));
+        }
+
+        let mut message = fields.message;
+
+        if !fields.extra.0.is_empty() {
+            message = format!("{} ({})", message, fields.extra);
+        }
+
+        self.watcher.lock().unwrap().push(level, message, context.join(":"), transfer);
+        (self.on_record)();
+    }
+}
+
+// For the app: everything we log goes to the watcher, and with `json` it's also printed to stdout,
+// one object per line with the full span list, for following a transfer from outside.
+pub fn install(watcher: WatcherLayer, json: bool) -> Result<(), EelError> {
+    let targets = || Targets::new().with_target(TARGET, Level::DEBUG);
+    let json_layer = json.then(|| tracing_subscriber::fmt::layer().json().with_span_list(true).with_filter(targets()));
+
+    tracing_subscriber::registry()
+        .with(watcher.with_filter(targets()))
+        .with(json_layer)
+        .try_init()
+        .map_err(|e| EelError::Io(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::field::Empty;
+
+    #[test]
+    fn test_spans_become_context() {
+        let watcher = Arc::new(Mutex::new(EelWatcher::new()));
+        watcher.lock().unwrap().level = LogLevel::Debug;
+        let subscriber = tracing_subscriber::registry().with(WatcherLayer::new(watcher.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("transfer", id = 12, peer = "10.0.0.2:5000", bytes = Empty);
+            let entered = span.enter();
+
+            tracing::debug!("Connecting...");
+            span.record("bytes", 2048);
+            tracing::info!(success = true, "Upload complete.");
+            tracing::warn!(attempt = 2, "Slow");
+            drop(entered);
+
+            tracing::info_span!(RECEIVE_SPAN, id = 12).in_scope(|| tracing::info!("Receiving..."));
+        });
+
+        // a plain message from the UI, nothing to follow
+        watcher.lock().unwrap().log("Saved contacts.");
+
+        let watcher = watcher.lock().unwrap();
+        let records = watcher.records();
+        assert_eq!(records[0].context, "transfer{id=12 peer=10.0.0.2:5000}");
+        assert_eq!(records[1].context, "transfer{id=12 peer=10.0.0.2:5000 bytes=2048}");
+        assert_eq!(records[1].level, LogLevel::Success);
+        assert_eq!(records[2].message, "Slow (attempt=2)");
+        assert!(records.range(..3).all(|record| record.transfer == Some(TransferId::Send(12))));
+        // the same number on the receiving side is a different transfer
+        assert_eq!(records[3].transfer, Some(TransferId::Receive(12)));
+        assert_eq!((records[4].transfer, records[4].context.as_str()), (None, ""));
+    }
+}
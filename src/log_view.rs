@@ -0,0 +1,193 @@
+use eel_file::eel_log::{EelWatcher, LogLevel, LogRecord, TransferId};
+use eframe::egui::{self, Color32, RichText, ScrollArea, Sense, Ui};
+use rfd::FileDialog;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+const SUCCESS_GREEN: Color32 = Color32::from_rgb(0x3c, 0xb3, 0x71);
+const LEVELS: [LogLevel; 5] = [LogLevel::Debug, LogLevel::Info, LogLevel::Success, LogLevel::Warn, LogLevel::Error];
+
+// The log under the status display. What's shown can be narrowed down by level, by transfer and by
+// text, and rows can be picked with a click (ctrl adds, shift picks a range) to copy them out.
+pub struct LogView {
+    shown_levels: [bool; LEVELS.len()],
+    transfer: Option<TransferId>,
+    search: String,
+    // records are picked by their seq, so the selection survives old ones falling off the front
+    selected: BTreeSet<u64>,
+    anchor: Option<u64>,
+}
+
+impl Default for LogView {
+    fn default() -> Self {
+        LogView {
+            shown_levels: [true; LEVELS.len()],
+            transfer: None,
+            search: String::new(),
+            selected: BTreeSet::new(),
+            anchor: None,
+        }
+    }
+}
+
+impl LogView {
+    pub fn show(&mut self, ui: &mut Ui, logger: &Arc<Mutex<EelWatcher>>) {
+        let to_save = self.show_records(ui, &mut logger.lock().unwrap());
+
+        // the dialog blocks until it's closed, anything logged meanwhile would be stuck waiting on the lock
+        match to_save.and_then(save_records) {
+            Some(Ok(path)) => logger.lock().unwrap().log(&format!("Saved the log to {}", path.display())),
+            Some(Err(e)) => logger.lock().unwrap().warn(&format!("Could not save the log: {}", e)),
+            None => {}
+        }
+    }
+
+    // draws everything, and hands back the shown lines when they should be saved
+    fn show_records(&mut self, ui: &mut Ui, watcher: &mut EelWatcher) -> Option<String> {
+        self.draw_filters(ui, watcher.records().iter().filter_map(|record| record.transfer).collect());
+
+        let shown: Vec<&LogRecord> = watcher.records().iter().filter(|record| self.matches(record)).collect();
+        let mut copy = false;
+        let mut save = false;
+        let mut clear = false;
+
+        ui.horizontal(|ui| {
+            copy = ui.add_enabled(!self.selected.is_empty(), egui::Button::new("Copy selection")).clicked();
+            save = ui.add_enabled(!shown.is_empty(), egui::Button::new("Save log as...")).clicked();
+            clear = ui.button("Clear").clicked();
+            ui.label(format!("{} of {} lines", shown.len(), watcher.records().len()));
+        });
+
+        // ctrl+c does the same as the button, unless it's meant for a text field
+        if !ui.ctx().wants_keyboard_input() && ui.input(|i| i.events.iter().any(|event| matches!(event, egui::Event::Copy))) {
+            copy |= !self.selected.is_empty();
+        }
+
+        if copy {
+            let text = shown.iter().filter(|record| self.selected.contains(&record.seq)).map(|record| record.to_string()).collect::<Vec<_>>();
+            ui.ctx().copy_text(text.join("\n"));
+        }
+
+        // in the same format as the log file
+        let to_save = save.then(|| shown.iter().map(|record| format!("{}\n", record)).collect());
+        self.draw_records(ui, &shown);
+
+        if clear {
+            watcher.clear();
+            self.selected.clear();
+            self.anchor = None;
+        }
+
+        to_save
+    }
+
+    fn draw_filters(&mut self, ui: &mut Ui, transfers: BTreeSet<TransferId>) {
+        ui.horizontal(|ui| {
+            for (shown, level) in self.shown_levels.iter_mut().zip(LEVELS) {
+                ui.checkbox(shown, level.to_string());
+            }
+
+            ui.separator();
+            egui::ComboBox::from_id_salt("log_transfer")
+                .selected_text(match self.transfer {
+                    Some(id) => id.to_string(),
+                    None => "All transfers".to_string(),
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.transfer, None, "All transfers");
+
+                    for id in transfers {
+                        ui.selectable_value(&mut self.transfer, Some(id), id.to_string());
+                    }
+                });
+
+            ui.separator();
+            ui.add(egui::TextEdit::singleline(&mut self.search).hint_text("Search").desired_width(160.0));
+
+            if !self.search.is_empty() && ui.small_button("x").clicked() {
+                self.search.clear();
+            }
+        });
+    }
+
+    fn matches(&self, record: &LogRecord) -> bool {
+        let level_shown = LEVELS.iter().zip(self.shown_levels).any(|(level, shown)| shown && *level == record.level);
+        let transfer_shown = self.transfer.is_none() || record.transfer == self.transfer;
+        // the context too, so a peer or file name finds everything logged while dealing with it
+        let search = self.search.to_lowercase();
+        let search_shown = search.is_empty() || record.message.to_lowercase().contains(&search) || record.context.to_lowercase().contains(&search);
+
+        level_shown && transfer_shown && search_shown
+    }
+
+    // the log takes up whatever height the window has left, only the rows in view get laid out
+    fn draw_records(&mut self, ui: &mut Ui, shown: &[&LogRecord]) {
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+
+        ScrollArea::both()
+            .auto_shrink([false; 2])
+            .stick_to_bottom(true)
+            .show_rows(ui, row_height, shown.len(), |ui, rows| {
+                for index in rows {
+                    let record = shown[index];
+                    // painted under the text once it's known how big the row turned out
+                    let background = ui.painter().add(egui::Shape::Noop);
+
+                    // wrapping would throw the row heights off, long lines scroll sideways instead
+                    let text = RichText::new(record.to_string()).monospace().color(level_colour(ui, record.level));
+                    let response = ui.add(egui::Label::new(text).extend().selectable(false).sense(Sense::click()));
+
+                    if response.clicked() {
+                        self.click(shown, index, ui.input(|i| i.modifiers));
+                    }
+
+                    if self.selected.contains(&record.seq) {
+                        ui.painter().set(background, egui::Shape::rect_filled(response.rect, 0.0, ui.visuals().selection.bg_fill));
+                    }
+                }
+            });
+    }
+
+    fn click(&mut self, shown: &[&LogRecord], index: usize, modifiers: egui::Modifiers) {
+        let seq = shown[index].seq;
+
+        if modifiers.shift && let Some(anchor) = self.anchor.and_then(|anchor| shown.iter().position(|record| record.seq == anchor)) {
+            let range = anchor.min(index)..=anchor.max(index);
+            self.selected = shown[range].iter().map(|record| record.seq).collect();
+            return;
+        }
+
+        if modifiers.command {
+            if !self.selected.remove(&seq) {
+                self.selected.insert(seq);
+            }
+        } else if self.selected.len() == 1 && self.selected.contains(&seq) {
+            self.selected.clear();
+        } else {
+            self.selected = BTreeSet::from([seq]);
+        }
+
+        self.anchor = Some(seq);
+    }
+}
+
+fn level_colour(ui: &Ui, level: LogLevel) -> Color32 {
+    match level {
+        LogLevel::Debug => ui.visuals().weak_text_color(),
+        LogLevel::Info => ui.visuals().text_color(),
+        LogLevel::Success => SUCCESS_GREEN,
+        LogLevel::Warn => ui.visuals().warn_fg_color,
+        LogLevel::Error => ui.visuals().error_fg_color,
+    }
+}
+
+// asks where `text` should go, None when the dialog is cancelled
+fn save_records(text: String) -> Option<std::io::Result<PathBuf>> {
+    let path = FileDialog::new()
+        .add_filter("log", &["log", "txt"])
+        .set_file_name("eel_file.log")
+        .save_file()?;
+
+    Some(std::fs::write(&path, text).map(|_| path))
+}
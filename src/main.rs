@@ -1,8 +1,20 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+mod control;
 mod controller;
+mod discovery;
+mod log_view;
 mod net_controller;
+mod notifications;
+mod parallel;
+mod port_mapping;
+mod protocol;
+mod transfer_code;
+mod transfer_queue;
+mod tray;
 mod ui_app;
+#[cfg(target_os = "linux")]
+mod zero_copy;
 mod normal_facts;
 mod sus_facts;
 mod insanity_facts;
@@ -11,7 +23,10 @@ mod amogus_facts;
 use crate::controller::Controller;
 use eframe::egui;
 use std::sync::{Arc, Mutex};
-use eel_file::eel_log::EelWatcher;
+use eel_file::eel_log::{EelWatcher, LogFile, MAX_LOG_FILE_SIZE};
+use eel_file::log_layer::{self, WatcherLayer};
+use eel_file::settings::{FactOdds, Settings};
+use eel_file::window_state::{WindowState, MIN_SIZE};
 use rand::prelude::*;
 use rand::rng;
 use crate::amogus_facts::AMOGUS_FACTS;
@@ -20,6 +35,7 @@ use crate::normal_facts::NORMAL_FACTS;
 use crate::sus_facts::SUS_FACTS;
 
 fn main() -> eframe::Result {
+    let settings = Settings::load(&Settings::default_path());
     let options = get_options();
 
     eframe::run_native(
@@ -29,14 +45,43 @@ fn main() -> eframe::Result {
             // This gives us image support:
             egui_extras::install_image_loaders(&cc.egui_ctx);
             
-            let watcher = Arc::new(Mutex::new(EelWatcher::new()));
+            let (settings, settings_error) = match settings {
+                Ok(settings) => (settings, None),
+                Err(e) => (Settings::default(), Some(e)),
+            };
+
+            let mut watcher = match LogFile::open(LogFile::default_path(), MAX_LOG_FILE_SIZE) {
+                Ok(log_file) => EelWatcher::new().with_file(log_file),
+                Err(e) => {
+                    let mut watcher = EelWatcher::new();
+                    watcher.warn(&format!("Could not open the log file, logging to the window only: {}", e));
+                    watcher
+                }
+            };
+            watcher.level = settings.log_level;
+
+            if let Some(e) = settings_error {
+                watcher.warn(&format!("Could not load the settings, using the defaults: {}", e));
+            }
+
+            let watcher = Arc::new(Mutex::new(watcher));
+
+            // --log-json prints everything as it's logged too, spans and all, one JSON object per line
+            let json = std::env::args().any(|arg| arg == "--log-json");
+            let repaint_context = cc.egui_ctx.clone();
+            let layer = WatcherLayer::new(watcher.clone()).on_record(move || repaint_context.request_repaint());
+
+            if let Err(e) = log_layer::install(layer, json) {
+                watcher.lock().unwrap().warn(&format!("Transfers won't show up in the log: {}", e));
+            }
+            ui_app::UiApp::apply_theme(&cc.egui_ctx, settings.theme);
             
             watcher.lock().unwrap().log("Welcome to EELFILE™ 🐍");
             watcher.lock().unwrap().log("Here is a random eel fact:");
-            watcher.lock().unwrap().log(display_eelfact());
+            watcher.lock().unwrap().log(display_eelfact(settings.fact_odds));
             
-            let controller = Controller::new(cc.egui_ctx.clone(), watcher.clone());
-            let ui_frame = ui_app::UiApp::new(controller, watcher.clone());
+            let controller = Controller::new(cc.egui_ctx.clone(), watcher.clone(), settings.notifications, settings.size_units);
+            let ui_frame = ui_app::UiApp::new(controller, watcher.clone(), settings);
 
             Ok(Box::new(ui_frame))
         }),
@@ -44,9 +89,17 @@ fn main() -> eframe::Result {
 }
 
 fn get_options() -> eframe::NativeOptions {
+    // a broken file shouldn't keep the app from starting, it just opens where the OS puts it
+    let window = WindowState::load(&WindowState::default_path()).unwrap_or_default();
+    let mut viewport = egui::ViewportBuilder::default();
+
+    if let Some(position) = window.position {
+        viewport = viewport.with_position(position);
+    }
     eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size([600.0, 530.0])
+        viewport: viewport
+            .with_inner_size(window.size)
+            .with_min_inner_size(MIN_SIZE)
             .with_icon(Arc::new(egui::IconData {
                 rgba: image::load_from_memory(include_bytes!("../assets/snek.png"))
                     .unwrap()
@@ -55,20 +108,33 @@ fn get_options() -> eframe::NativeOptions {
                 width: 512,
                 height: 512,
             }))
-            .with_resizable(false).with_maximize_button(false),
+            .with_resizable(true)
+            .with_drag_and_drop(true),
         renderer: eframe::Renderer::Glow,
         ..Default::default()
     }
 }
 
-fn display_eelfact() -> &'static str {
+fn display_eelfact(odds: FactOdds) -> &'static str {
     let mut rng = rng();
     let roll: u8 = rng.random_range(0..100);
 
-    match roll {
-        0..=49 => NORMAL_FACTS.choose(&mut rng).unwrap(),
-        50..=89 => SUS_FACTS.choose(&mut rng).unwrap(),
-        90..=98 => AMOGUS_FACTS.choose(&mut rng).unwrap(),
-        _ => INSANITY_FACTS.choose(&mut rng).unwrap()
+    // the odds add up to 100, so every roll lands somewhere
+    let facts = [
+        (odds.normal, NORMAL_FACTS),
+        (odds.sus, SUS_FACTS),
+        (odds.amogus, AMOGUS_FACTS),
+        (odds.insanity, INSANITY_FACTS),
+    ];
+    let mut below = 0;
+
+    for (chance, facts) in facts {
+        below += chance;
+
+        if roll < below {
+            return facts.choose(&mut rng).unwrap();
+        }
     }
+
+    INSANITY_FACTS.choose(&mut rng).unwrap()
 }
\ No newline at end of file
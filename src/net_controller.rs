@@ -1,28 +1,85 @@
 use eel_file::AppState::*;
-use eel_file::{Animation, AppEvent, FileInfo, Util};
+use eel_file::{AppEvent, AppState, Compression, FileInfo, SizeUnits, Util};
 use std::io::{Error, ErrorKind};
+use std::future::Future;
 use std::net::{SocketAddr, SocketAddrV4};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use sysinfo::Disks;
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::runtime::{Builder, Runtime};
-use tokio::select;
+use tokio::{join, select};
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
-use eel_file::Animation::{IdleAfterError, IdleAfterSuccess};
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+use crate::discovery::{self, Announcement, PeerList, DISCOVERY_GROUP, DISCOVERY_PORT};
+use crate::transfer_code::{SessionKey, Side, TransferCode};
+use eel_file::relay::{self, RelayConfig, RelayRole};
+use crate::port_mapping::{PortMapping, RENEW_INTERVAL};
+use crate::control::TransferControl;
+use crate::transfer_queue::{JobId, SharedQueue, MAX_RUNNING_SENDS};
+use crate::parallel::{self, Tuning, MAX_STREAMS};
+use crate::protocol::{self, Acceptance, FrameKind, Outcome, CHUNK_SIZE};
+use eel_file::EelError;
+use eel_file::log_layer::{RECEIVE_SPAN, TRANSFER_SPAN};
+use eel_file::settings::ConflictPolicy;
+use eel_file::state_machine::{Ending, StateMachine};
 
 type CancelToken = Arc<Mutex<Option<CancellationToken>>>;
+// the transfer that's moving data right now, if any, so it can be paused from the UI
+type CurrentTransfer = Arc<Mutex<Option<TransferControl>>>;
 
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+// numbers every accepted connection for the log, across listeners so restarting one doesn't reuse them
+static RECEIVED: AtomicU64 = AtomicU64::new(0);
+
+// info unless a level is given, like log!(Warn: "..."), Success is info that shows up green in the log
 macro_rules! log {
-    ($tx:expr, $($arg:tt)*) => {
-        $tx.send(AppEvent::StatusMessage(format!($($arg)*))).unwrap();
-    };
+    (Success: $($arg:tt)*) => { tracing::info!(success = true, $($arg)*) };
+    (Debug: $($arg:tt)*) => { tracing::debug!($($arg)*) };
+    (Info: $($arg:tt)*) => { tracing::info!($($arg)*) };
+    (Warn: $($arg:tt)*) => { tracing::warn!($($arg)*) };
+    (Error: $($arg:tt)*) => { tracing::error!($($arg)*) };
+    ($($arg:tt)*) => { tracing::info!($($arg)*) };
+}
+
+// one listener or send, every state change it makes is checked and goes out together with its animation
+struct Status {
+    tx: UnboundedSender<AppEvent>,
+    machine: StateMachine,
+}
+
+impl Status {
+    fn new(tx: UnboundedSender<AppEvent>) -> Status {
+        Status { tx, machine: StateMachine::new() }
+    }
+
+    fn go(&mut self, state: AppState) {
+        match self.machine.go(state) {
+            Ok(_) => self.publish(),
+            // a bug somewhere, but not one worth taking the transfer down for
+            Err(e) => {
+                log!(Error: "{}", e);
+            }
+        }
+    }
+
+    fn finish(&mut self, ending: Ending) {
+        self.machine.finish(ending);
+        self.publish();
+    }
+
+    fn publish(&self) {
+        let _ = self.tx.send(AppEvent::AppState(self.machine.state().clone()));
+        let _ = self.tx.send(AppEvent::Animate(self.machine.animation()));
+    }
 }
 
 pub struct NetController {
@@ -30,11 +87,66 @@ pub struct NetController {
     worker: Option<JoinHandle<()>>,
     server_token: Option<CancellationToken>,
     task_token: CancelToken,
+    transfer: CurrentTransfer,
 }
 
 pub enum NetCommand {
-    Send(SocketAddrV4, FileInfo),
-    Receive(PathBuf, u16),
+    Receive(PathBuf, u16, ListenOptions),
+}
+
+#[derive(Clone, Default)]
+pub struct SendOptions {
+    pub code: Option<TransferCode>,
+    // when set, the address passed with Send is ignored and we go through the relay instead
+    pub relay: Option<RelayConfig>,
+    // parallel connections to spread the file over, the receiver may hand out fewer
+    pub streams: u8,
+    pub tuning: Tuning,
+    // what the log shows sizes and rates in
+    pub size_units: SizeUnits,
+}
+
+#[derive(Clone, Default)]
+pub struct ListenOptions {
+    pub announce: bool,
+    // a code makes the listener one-shot, it shuts down after the first connection attempt
+    pub code: Option<TransferCode>,
+    pub relay: Option<RelayConfig>,
+    // UPnP IGD mapping for the listening port, removed again when the listener stops
+    pub map_port: bool,
+    pub tuning: Tuning,
+    pub conflict: ConflictPolicy,
+    pub size_units: SizeUnits,
+}
+
+// where incoming connections come from: our own port, or a relay we keep dialing out to
+enum ConnectionSource {
+    Direct(TcpListener),
+    Relay(RelayConfig),
+}
+
+impl ConnectionSource {
+    // extra connections of a parallel transfer can only come in on our own port
+    fn join_listener(&self) -> Option<&TcpListener> {
+        match self {
+            ConnectionSource::Direct(listener) => Some(listener),
+            ConnectionSource::Relay(_) => None,
+        }
+    }
+
+    async fn next(&self) -> Result<(TcpStream, String), EelError> {
+        match self {
+            ConnectionSource::Direct(listener) => loop {
+                if let Ok((stream, addr)) = listener.accept().await {
+                    return Ok((stream, addr.to_string()));
+                }
+            },
+            ConnectionSource::Relay(relay) => {
+                let stream = relay::connect_via_relay(relay, RelayRole::Listener).await?;
+                Ok((stream, format!("relay session {}", relay.session)))
+            }
+        }
+    }
 }
 
 impl NetController {
@@ -46,6 +158,7 @@ impl NetController {
             worker: None,
             server_token: None,
             task_token: Arc::new(Mutex::new(None)),
+            transfer: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -53,22 +166,7 @@ impl NetController {
         let (tx, rx) = mpsc::unbounded_channel();
 
         match cmd {
-            NetCommand::Send(addr, file_info) => {
-                let task_token = CancellationToken::new();
-
-                self.task_token.lock().unwrap().replace(task_token.clone());
-
-                let futures_rewritten = self.runtime.as_ref().unwrap().spawn(Self::send(
-                    tx,
-                    task_token.clone(),
-                    addr,
-                    file_info,
-                ));
-                self.worker = Some(futures_rewritten);
-                rx
-            }
-
-            NetCommand::Receive(path, port) => {
+            NetCommand::Receive(path, port, options) => {
                 let server_token = CancellationToken::new();
                 let task_token = CancellationToken::new();
                 self.server_token = Some(server_token.clone());
@@ -78,9 +176,11 @@ impl NetController {
                     tx,
                     server_token.clone(),
                     self.task_token.clone(),
+                    self.transfer.clone(),
                     path,
                     port,
-                ));
+                    options,
+                ).instrument(tracing::info_span!("listener", port)));
 
                 self.worker = Some(futures_rewritten);
                 rx
@@ -88,63 +188,233 @@ impl NetController {
         }
     }
 
+    // Sends don't go through `start`, they wait in the queue and get started here, as many at once as
+    // MAX_RUNNING_SENDS allows. Each one reports into `events` with its job ID and starts the next when it's done.
+    pub fn start_queued_sends(&self, queue: SharedQueue, events: UnboundedSender<(JobId, AppEvent)>) {
+        let _guard = self.runtime.as_ref().unwrap().enter();
+        Self::dispatch(queue, events);
+    }
+
+    fn dispatch(queue: SharedQueue, events: UnboundedSender<(JobId, AppEvent)>) {
+        let startable = queue.lock().unwrap().take_startable(MAX_RUNNING_SENDS);
+
+        for job in startable {
+            let queue = queue.clone();
+            let events = events.clone();
+
+            tokio::spawn(async move {
+                let (tx, mut rx) = mpsc::unbounded_channel();
+                // the queue's own id, the log viewer filters by it
+                let span = tracing::info_span!(
+                    TRANSFER_SPAN,
+                    id = job.id,
+                    peer = %job.addr,
+                    file = job.file_info.name.as_str(),
+                    size = job.file_info.size,
+                    bytes = Empty,
+                );
+                let send = Self::send(tx, job.token, job.transfer, job.addr, job.file_info, job.options).instrument(span);
+
+                let report = async {
+                    while let Some(event) = rx.recv().await {
+                        queue.lock().unwrap().apply(job.id, &event);
+                        let _ = events.send((job.id, event));
+                    }
+                };
+
+                // a send of its own, so even a panic in it ends up here as a failed job instead of holding its slot forever
+                let (sent, _) = join!(tokio::spawn(send), report);
+
+                if let Err(e) = sent {
+                    log!(Error: "Send #{} stopped unexpectedly: {}", job.id, e);
+                }
+
+                queue.lock().unwrap().finish(job.id);
+                Self::dispatch(queue, events);
+            });
+        }
+    }
+
+    // runs for the lifetime of the app, the UI just reads whatever ends up in `peers`
+    pub fn start_discovery(&mut self, peers: PeerList, on_change: impl Fn() + Send + 'static) -> Result<(), Error> {
+        let runtime = self.runtime.as_ref().unwrap();
+        let socket = {
+            let _guard = runtime.enter();
+            discovery::multicast_socket()?
+        };
+
+        runtime.spawn(discovery::browse(socket, peers, CancellationToken::new(), on_change));
+        Ok(())
+    }
+
+    // stops everything and gives the listener a moment to clean up after itself (port mappings mostly)
+    pub fn shutdown(&mut self) {
+        if let Some(token) = self.server_token.take() {
+            token.cancel();
+        }
+
+        if let Some(token) = self.task_token.lock().unwrap().take() {
+            token.cancel();
+        }
+
+        if let Some(worker) = self.worker.take() {
+            let _ = self
+                .runtime
+                .as_ref()
+                .unwrap()
+                .block_on(tokio::time::timeout(Duration::from_secs(3), worker));
+        }
+    }
+
     pub fn abort_task(&mut self) {
-        self.task_token.lock().unwrap().take().unwrap().cancel();
+        if let Some(token) = self.task_token.lock().unwrap().take() {
+            token.cancel();
+        }
+    }
+
+    // both ends stop sending data but keep the connections open, the other side gets told about it
+    pub fn pause_task(&mut self) {
+        if let Some(transfer) = self.transfer.lock().unwrap().as_ref() {
+            transfer.pause();
+        }
+    }
+
+    pub fn resume_task(&mut self) {
+        if let Some(transfer) = self.transfer.lock().unwrap().as_ref() {
+            transfer.resume();
+        }
     }
 
     pub fn abort_server(&mut self) {
-        self.server_token.take().unwrap().cancel();
-        self.task_token.lock().unwrap().take().unwrap().cancel();
+        if let Some(token) = self.server_token.take() {
+            token.cancel();
+        }
+
+        self.abort_task();
     }
 
     async fn listen(
         tx: UnboundedSender<AppEvent>,
         server_token: CancellationToken,
         task_token_ref: CancelToken,
+        transfer: CurrentTransfer,
         path: PathBuf,
         port: u16,
+        options: ListenOptions,
     ) {
-        let addr: SocketAddr = format!("0.0.0.0:{}", port).parse().unwrap();
-        let listener = TcpListener::bind(addr).await;
+        let mut status = Status::new(tx.clone());
+        let mut mapping: Option<PortMapping> = None;
+
+        let source = match &options.relay {
+            Some(relay) => {
+                status.go(Listening);
+                log!("Listening through relay {} with session {}...", relay.addr, relay.session);
+                ConnectionSource::Relay(relay.clone())
+            }
+            None => {
+                let addr: SocketAddr = format!("0.0.0.0:{}", port).parse().unwrap();
+                let listener = TcpListener::bind(addr).await;
+
+                if listener.is_err() {
+                    status.finish(Ending::Failure);
+                    log!(Error: "Could not listen: port most likely already in use.");
+                    return;
+                }
 
-        if let Err(_) = listener {
-            let _ = tx.send(AppEvent::AppState(Idle));
-            let _ = tx.send(AppEvent::Animate(IdleAfterError));
-            log!(tx, "Could not listen: port most likely already in use.");
-            return;
-        }
+                status.go(Listening);
+
+                log!("Listening on port {}...", addr.port());
 
-        let listener = listener.unwrap();
+                if options.announce {
+                    let target = SocketAddrV4::new(DISCOVERY_GROUP, DISCOVERY_PORT);
+                    let nameplate = options.code.as_ref().map(|code| code.nameplate);
+                    tokio::spawn(discovery::announce(Announcement::new(port, nameplate), target, server_token.child_token()));
+                    log!(Debug: "Announcing this listener on the local network.");
+                }
+
+                if options.map_port {
+                    log!(Debug: "Asking the gateway to forward port {}...", port);
 
-        tx.send(AppEvent::AppState(Listening)).unwrap();
-        let _ = tx.send(AppEvent::Animate(Animation::Listening));
+                    match PortMapping::create(port).await {
+                        Ok(created) => {
+                            log!(Success: "Port mapped. Peers outside the LAN can send to {}", created.external_addr);
+                            mapping = Some(created);
+                        }
+                        Err(e) => {
+                            log!(Warn: "{}. Peers outside the LAN probably can't reach you.", e);
+                        }
+                    }
+                }
 
-        log!(tx, "Listening on port {}...", addr.port());
+                ConnectionSource::Direct(listener.unwrap())
+            }
+        };
 
-        let task_token = CancellationToken::new();
+        let mut task_token = CancellationToken::new();
 
         task_token_ref.lock().unwrap().replace(task_token.clone());
 
+        let mut renew_timer = tokio::time::interval_at(tokio::time::Instant::now() + RENEW_INTERVAL, RENEW_INTERVAL);
+
         loop {
             select! {
+                _ = renew_timer.tick(), if mapping.is_some() => {
+                    if let Err(e) = mapping.as_ref().unwrap().renew().await {
+                        log!(Warn: "Could not renew the port mapping: {}", e);
+                    }
+                }
+
                 _ = server_token.cancelled() => {
-                    let _ = tx.send(AppEvent::AppState(Idle));
-                    let _ = tx.send(AppEvent::Animate(Animation::Idle));
-                    log!(tx, "Listener shut down.");
+                    status.finish(Ending::Quietly);
+                    log!("Listener shut down.");
                     break;
                 },
 
-                Ok((stream, addr)) = listener.accept() => {
-                    let _ = tx.send(AppEvent::AppState(Handshake));
-                    let _ = tx.send(AppEvent::Animate(Animation::Handshake));
-                    log!(tx, "Accepted connection from {}", addr);
+                next = source.next() => {
+                    let (stream, addr) = match next {
+                        Ok(connection) => connection,
+                        Err(e) => {
+                            status.finish(Ending::Failure);
+                            log!(Error: "Listener stopped: {}", e);
+                            break;
+                        }
+                    };
+
+                    // everything from the handshake to the file being stored happens in here
+                    let id = RECEIVED.fetch_add(1, Ordering::Relaxed) + 1;
+                    let connection = tracing::info_span!(RECEIVE_SPAN, id, peer = %addr, file = Empty, size = Empty, bytes = Empty);
+                    status.go(Handshake);
+                    connection.in_scope(|| log!(Debug: "Accepted connection from {}", addr));
+
+                    if options.code.is_some() {
+                        let stored = Self::handle_rx_stream(stream, source.join_listener(), &options, task_token.clone(), transfer.clone(), path.clone(), &mut status)
+                            .instrument(connection)
+                            .await;
+
+                        status.finish(if stored { Ending::Success } else { Ending::Failure });
+                        log!("The transfer code has been used up, listener shut down.");
+                        break;
+                    }
+
                     // if in the future I want to listen to new connections and tell them to fuck off, this is where I'd do it
-                    Self::handle_rx_stream(stream, task_token.clone(), path.clone(), tx.clone()).await;
-                    log!(tx, "Communication ended with {}, returning to listening.", addr);
-                    let task_token = CancellationToken::new();
+                    Self::handle_rx_stream(stream, source.join_listener(), &options, task_token.clone(), transfer.clone(), path.clone(), &mut status)
+                        .instrument(connection.clone())
+                        .await;
+                    connection.in_scope(|| log!("Communication ended with {}, returning to listening.", addr));
+                    task_token = CancellationToken::new();
                     task_token_ref.lock().unwrap().replace(task_token.clone());
-                    let _ = tx.send(AppEvent::AppState(Listening));
-                    let _ = tx.send(AppEvent::Animate(Animation::Listening));
+                    status.go(Listening);
+                }
+            }
+        }
+
+        if let Some(mapping) = mapping {
+            match mapping.remove().await {
+                Ok(_) => {
+                    log!("Port mapping removed from the gateway.");
+                }
+                Err(e) => {
+                    log!(Warn: "Could not remove the port mapping: {}", e);
                 }
             }
         }
@@ -152,293 +422,684 @@ impl NetController {
 
     async fn handle_rx_stream(
         mut stream: TcpStream,
+        joins: Option<&TcpListener>,
+        options: &ListenOptions,
         shutdown_token: CancellationToken,
+        transfer: CurrentTransfer,
         destination_path_buf: PathBuf,
-        tx: UnboundedSender<AppEvent>,
-    ) {
-        log!(tx, "Attempting to retrieve metadata...");
-        let mut buffer = BufReader::new(&mut stream);
-        let mut metadata_lines = (&mut buffer).lines();
-        let mut metadata = String::new();
+        status: &mut Status,
+    ) -> bool {
+        let tx = status.tx.clone();
+        let tuning = options.tuning;
+
+        let key = match &options.code {
+            Some(code) => match code.handshake(&mut stream, Side::Receiver).await {
+                Ok(key) => {
+                    log!("Transfer code accepted.");
+                    Some(key)
+                }
+                Err(e) => {
+                    log!(Warn: "{}", e);
+                    return false;
+                }
+            },
+            None => None,
+        };
+        let key = key.as_ref();
 
-        while let Some(line) = metadata_lines.next_line().await.unwrap() {
-            if line == "ITS OVER".to_string() {
-                break;
-            }
-            metadata += &line;
-        }
-        
-        // ugh jank ugh
-        drop(metadata_lines);
-        drop(buffer);
+        log!(Debug: "Attempting to retrieve metadata...");
 
-        let mut file_info: FileInfo = serde_json::from_str(&metadata).unwrap();
-        log!(tx, "Received file info. Name: {}, size: {}", file_info.name, Util::display_size(file_info.size));
+        let mut file_info = match tuning.or_stall(Self::read_offer(&mut stream, key)).await {
+            Ok(file_info) => file_info,
+            Err(e) => {
+                log!(Warn: "Could not read the file info: {}", e);
+                return false;
+            }
+        };
+        Span::current().record("file", file_info.name.as_str()).record("size", file_info.size);
+        log!(Debug: "Received file info. Name: {}, size: {}", file_info.name, Util::format_size(file_info.size, options.size_units));
+
+        // the name comes from the other end, it doesn't get to pick where in our filesystem the file lands
+        let Some(name) = Self::safe_file_name(&file_info.name) else {
+            let _ = stream.write_all(b"NO, SIRE.\r\n").await;
+            log!(Warn: "The sender asked to save the file as \"{}\", the file was refused.", file_info.name);
+            return false;
+        };
+        file_info.name = name;
 
         tx.send(AppEvent::FileInfo(file_info.clone())).unwrap();
 
-        let mut file_to_create = destination_path_buf.clone();
-        file_to_create.push(&file_info.name);
+        let Some(destination) = Self::resolve_conflict(destination_path_buf.join(&file_info.name), options.conflict) else {
+            let _ = stream.write_all(b"NO, SIRE.\r\n").await;
+            log!(Warn: "{} is already in the folder, the file was refused.", file_info.name);
+            return false;
+        };
 
-        file_info.path = Some(file_to_create);
+        if destination.file_name() != Some(file_info.name.as_ref()) {
+            log!("{} is already in the folder, saving it as {} instead.", file_info.name, destination.display());
+        }
+
+        // received next to where it's going and only moved there once the sender vouched for it,
+        // so whatever was there before (with Overwrite) stays untouched until then
+        file_info.path = Some(destination_path_buf.join(format!(".{}.{}.eelpart", file_info.name, parallel::new_session())));
 
         if !NetController::is_enough_space(&destination_path_buf, file_info.size) {
             let res = stream
-                .write(b"NO, SIRE.\r\n")
+                .write_all(b"NO, SIRE.\r\n")
                 .await;
 
-            if let Err(_) = res {
-                log!(tx, "Could not write to stream. This is HIGHLY unlikely at this point. :)");
-                tx.send(AppEvent::AppState(Idle)).unwrap();
-                let _ = tx.send(AppEvent::Animate(IdleAfterError));
-                return;
+            if res.is_err() {
+                log!(Error: "Could not write to stream. This is HIGHLY unlikely at this point. :)");
+                return false;
             }
 
-            log!(tx, "You don't have enough space for the incoming file. Connection aborted.");
-            return;
+            log!(Warn: "You don't have enough space for the incoming file. Connection aborted.");
+            return false;
         }
 
-        let file_result = NetController::create_file(file_info.clone()).await;
-
-        if let Err(e) = file_result {
-            log!(tx, "Failed to create file for the following reason: {}", e);
-            return;
+        if let Err(e) = NetController::create_file(file_info.clone()).await {
+            log!(Error: "Failed to create file for the following reason: {}", e);
+            return false;
         }
 
-        let file_result = file_result.unwrap();
+        let streams = match joins {
+            Some(_) => file_info.streams.clamp(1, MAX_STREAMS),
+            None => 1,
+        };
+
+        // we can decompress everything we know about, so whatever the sender offers is fine
+        let acceptance = Acceptance {
+            compression: file_info.compression.clone(),
+            streams,
+            session: (streams > 1).then(parallel::new_session),
+        };
 
-        stream.write(b"HAND IT OVER\r\n").await.unwrap();
+        if let Err(e) = stream.write_all(acceptance.to_line().as_bytes()).await {
+            log!(Error: "Could not accept the file, the sender is gone: {}", e);
+            let _ = tokio::fs::remove_file(file_info.path.as_ref().unwrap()).await;
+            return false;
+        }
 
-        Self::accept_file(
-            tx.clone(),
-            &mut stream,
-            file_result,
-            file_info,
-            shutdown_token.clone(),
+        // cancelling goes through the ranges so the sender hears about it
+        let control = TransferControl::new(shutdown_token.child_token());
+        transfer.lock().unwrap().replace(control.clone());
+
+        let received = Self::accept_file(
+            status,
+            stream,
+            joins,
+            file_info.clone(),
+            acceptance,
+            options,
+            control,
         )
         .await;
+
+        transfer.lock().unwrap().take();
+
+        let partial = file_info.path.replace(destination.clone()).unwrap();
+
+        let mut stored = match received {
+            Some(primary) => Self::confirm_stored(primary, &partial, tuning, key).await,
+            None => false,
+        };
+
+        if stored && let Err(e) = tokio::fs::rename(&partial, &destination).await {
+            log!(Error: "Could not move the file to {}: {}", destination.display(), e);
+            let _ = tokio::fs::remove_file(&partial).await;
+            stored = false;
+        }
+
+        if stored {
+            tx.send(AppEvent::Stored(file_info)).unwrap();
+        } else {
+            tx.send(AppEvent::Failed(file_info)).unwrap();
+        }
+
+        stored
     }
 
-    async fn accept_file(
-        tx: UnboundedSender<AppEvent>,
-        stream: &mut TcpStream,
-        mut file_handle: File,
-        file_info: FileInfo,
-        shutdown_token: CancellationToken,
-    ) {
-        let size = file_info.size;
-        let mut remaining_size = size;
-        let file_path = file_info.path.clone().unwrap();
-        let mut buffer = vec![0u8; 64 * 1024];
+    // fsync, hash and tell the sender what we've got, then wait for it to say whether that matches its copy
+    async fn confirm_stored(mut primary: TcpStream, file_path: &Path, tuning: Tuning, key: Option<&SessionKey>) -> bool {
+        let outcome = match Self::sync_and_hash(file_path).await {
+            Ok(digest) => Outcome::Stored(digest),
+            Err(e) => Outcome::Failed(e.to_string()),
+        };
 
-        tx.send(AppEvent::AppState(Accepting)).unwrap();
-        let _ = tx.send(AppEvent::Animate(Animation::Accepting));
-        log!(tx, "File transfer starting...");
+        let line = protocol::seal_line(outcome.to_line().trim_end(), key, "outcome");
+        let told = tuning.or_stall(primary.write_all(line.as_bytes())).await;
 
-        loop {
-            select! {
-                _ = shutdown_token.cancelled() => {
-                    tx.send(AppEvent::AppState(Idle)).unwrap();
-                    let _ = tx.send(AppEvent::Animate(IdleAfterError));
-                    log!(tx, "File download cancelled.");
-                    // cleanup (I should be making invisible temp files but whatever)
-                    drop(file_handle);
-                    let _ = std::fs::remove_file(file_path).expect("Couldn't remove file!!!");
-                    break;
+        let digest = match outcome {
+            Outcome::Stored(digest) => digest,
+            Outcome::Failed(reason) => {
+                log!(Error: "Could not store the file: {}", reason);
+                let _ = std::fs::remove_file(file_path);
+                return false;
+            }
+        };
+
+        // the verdict is sealed over our digest, a verdict meant for some other copy doesn't count
+        let context = format!("verdict {}", digest);
+
+        let mut verdict = String::new();
+        let verdict = match told {
+            Ok(_) => match tuning.or_stall(BufReader::new(&mut primary).read_line(&mut verdict)).await {
+                Ok(_) => protocol::open_line(&verdict, key, &context).unwrap_or(""),
+                Err(_) => "",
+            },
+            Err(_) => "",
+        };
+
+        match verdict {
+            protocol::VERIFIED => {
+                log!(Success: "File transfer complete. The sender confirmed the checksum.");
+                true
+            }
+            protocol::MISMATCH => {
+                log!(Error: "The file got corrupted on the way (checksum mismatch), deleting it.");
+                let _ = std::fs::remove_file(file_path);
+                false
+            }
+            // the sender counts it as failed without our digest reaching it, so keeping it here would be lying to one of us
+            _ => {
+                log!(Error: "The sender never confirmed the checksum, deleting the file.");
+                let _ = std::fs::remove_file(file_path);
+                false
+            }
+        }
+    }
+
+    async fn sync_and_hash(file_path: &Path) -> Result<String, Error> {
+        File::options().write(true).open(file_path).await?.sync_all().await?;
+        protocol::file_digest(file_path.to_path_buf()).await
+    }
+
+    // the offer is the FileInfo json and an "ITS OVER" line, the sender waits for our answer before sending anything else.
+    // With a transfer code that last line carries a seal over the json, so nobody else's offer gets in.
+    async fn read_offer(stream: &mut TcpStream, key: Option<&SessionKey>) -> Result<FileInfo, Error> {
+        let mut lines = BufReader::new(stream).lines();
+        let mut metadata = String::new();
+
+        while let Some(line) = lines.next_line().await? {
+            if line.starts_with("ITS OVER") {
+                if protocol::open_line(&line, key, &format!("offer {}", metadata)) != Some("ITS OVER") {
+                    return Err(Error::new(ErrorKind::InvalidData, "the offer isn't sealed with the transfer code"));
                 }
 
-                _ = stream.readable() => {
-                    match stream.try_read(&mut buffer) {
-                        Ok(0) => {
-                            log!(tx, "Unexpected end of file: connection was unexpectedly terminated!");
-                            drop(file_handle);
-                            let _ = std::fs::remove_file(file_path).expect("Couldn't remove file!!!");
-                            break;
-                        }
+                return serde_json::from_str(&metadata).map_err(|e| Error::new(ErrorKind::InvalidData, e));
+            }
 
-                        Ok(bytes) => {
-                            if bytes == 0 {
-                            log!(tx, "Download interrupted unexpectedly. It's over.");
-                            break;
-                            }
+            metadata += &line;
+        }
 
-                            // todo: better error handling? this is gonna crash but it shouldn't really happen since I'm checking for permissions
-                            file_handle.write_all(&buffer[..bytes]).await.expect("Couldn't write to file");
-                            remaining_size -= bytes as u64;
-                            tx.send(AppEvent::Progress(1.0 - (remaining_size as f32 / file_info.size as f32))).unwrap();
+        Err(Error::new(ErrorKind::UnexpectedEof, "the sender hung up halfway through"))
+    }
 
-                            if remaining_size == 0 {
-                                log!(tx, "File transfer complete.");
-                            break;
-                            }
-                        }
+    // brings the whole file in, what's left of the primary connection is handed back for confirming it
+    async fn accept_file(
+        status: &mut Status,
+        stream: TcpStream,
+        joins: Option<&TcpListener>,
+        file_info: FileInfo,
+        acceptance: Acceptance,
+        options: &ListenOptions,
+        control: TransferControl,
+    ) -> Option<TcpStream> {
+        let tx = status.tx.clone();
+        let units = options.size_units;
+        let file_path = file_info.path.clone().unwrap();
 
-                        Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                            // println!("No data");
-                            continue;
-                        }
+        status.go(Accepting);
 
-                        Err(_) => {
-                            log!(tx, "Connection was unexpectedly terminated!");
-                            break;
-                        }
+        let connections = match (&acceptance.session, joins) {
+            (Some(session), Some(listener)) => {
+                log!(Debug: "Waiting for the sender to open {} more connection(s)...", acceptance.streams - 1);
+
+                let joined = select! {
+                    _ = control.cancelled() => Err(Error::new(ErrorKind::Interrupted, "cancelled")),
+                    joined = parallel::accept_joins(listener, stream, session, acceptance.streams) => joined,
+                };
+
+                match joined {
+                    Ok(connections) => connections,
+                    Err(e) => {
+                        log!(Error: "File download aborted: {}", e);
+                        let _ = std::fs::remove_file(file_path);
+                        return None;
                     }
                 }
             }
+            _ => vec![stream],
+        };
+
+        log!(Debug: "File transfer starting...");
+
+        let progress = Arc::new(AtomicU64::new(0));
+        let mut receiving = Box::pin(parallel::receive_all(connections, file_path.clone(), file_info.size, options.tuning, control.clone(), progress.clone()));
+        let started = Instant::now();
+        let result = Self::follow_progress(status, &mut receiving, &control, &progress, file_info.size, Accepting).await;
+        drop(receiving);
+
+        match result {
+            Ok((primary, wire_bytes)) => {
+                tx.send(AppEvent::Progress(1.0)).unwrap();
+                Span::current().record("bytes", wire_bytes);
+                log!("All data received at {}. {} came over the wire, compression ratio {:.2}:1.",
+                    Self::average_rate(file_info.size, started, units), Util::format_size(wire_bytes, units), protocol::compression_ratio(file_info.size, wire_bytes));
+                Some(primary)
+            }
+            Err(e) => {
+                match e.kind() {
+                    ErrorKind::Interrupted => {
+                        log!("File download cancelled.");
+                    }
+                    ErrorKind::ConnectionAborted => {
+                        log!(Warn: "Download stopped, {}.", e);
+                    }
+                    ErrorKind::TimedOut => {
+                        log!(Error: "Download aborted: {}.", e);
+                    }
+                    _ => {
+                        log!(Error: "Connection was unexpectedly terminated! Error: {}", e);
+                    }
+                }
+
+                // the partial file never got a real name, nothing else to clean up
+                let _ = std::fs::remove_file(file_path);
+                None
+            }
         }
     }
 
     async fn send(
         tx: UnboundedSender<AppEvent>,
         task_token: CancellationToken,
+        transfer: CurrentTransfer,
         addr: SocketAddrV4,
-        file_info: FileInfo,
+        mut file_info: FileInfo,
+        options: SendOptions,
     ) {
-        let _ = tx.send(AppEvent::AppState(Connecting));
-        log!(tx, "Attempting to establish TCP connection to {}...", addr);
-        let _ = tx.send(AppEvent::Animate(Animation::Connecting));
+        // going through the relay there's nowhere for the extra connections to go
+        let join_addr = options.relay.is_none().then_some(addr);
+        file_info.streams = match join_addr {
+            Some(_) => options.streams.clamp(1, MAX_STREAMS),
+            None => 1,
+        };
 
-        loop {
-            select! {
-                _ = task_token.cancelled() => {
-                    let _ = tx.send(AppEvent::AppState(Idle));
-                    let _ = tx.send(AppEvent::Animate(Animation::Idle));
-                    log!(tx, "Connection aborted manually by user.");
-                    break;
-                }
+        let mut status = Status::new(tx.clone());
+        status.go(Connecting);
+        match &options.relay {
+            Some(relay) => {
+                log!(Debug: "Connecting through relay {}, waiting for the receiver to join session {}...", relay.addr, relay.session);
+            }
+            None => {
+                log!(Debug: "Attempting to establish TCP connection to {}...", addr);
+            }
+        }
 
-                conn = TcpStream::connect(addr) => {
-                    match conn {
-                        Ok(stream) => {
-                            Self::handle_send_request(stream, tx.clone(), task_token.clone(), file_info).await;
-                            let _ = tx.send(AppEvent::Animate(IdleAfterSuccess));
-                            let _ = tx.send(AppEvent::AppState(Idle));
-                            break;
-                        }
-                        Err(e) => {
-                            let _ = tx.send(AppEvent::AppState(Idle));
-                            let _ = tx.send(AppEvent::Animate(IdleAfterError));
-                            log!(tx, "Connection closed with error: {}", e);
-                            break;
-                        }
-                    }
-                }
+        let conn = select! {
+            _ = task_token.cancelled() => {
+                status.finish(Ending::Quietly);
+                log!("Connection aborted manually by user.");
+                return;
             }
+
+            conn = Self::connect(addr, &options) => conn,
+        };
+
+        // from here on cancelling is handled inside, the receiver has to be told about it
+        match conn {
+            Ok(stream) => {
+                let delivered = Self::handle_send_request(stream, join_addr, &options, &mut status, task_token.clone(), transfer, file_info).await;
+
+                // only the receiver saying it stored a matching copy counts as success
+                status.finish(if delivered { Ending::Success } else { Ending::Failure });
+            }
+            Err(e) => {
+                status.finish(Ending::Failure);
+                log!(Error: "Connection closed with error: {}", e);
+            }
+        }
+    }
+
+    async fn connect(addr: SocketAddrV4, options: &SendOptions) -> Result<TcpStream, EelError> {
+        match &options.relay {
+            Some(relay) => relay::connect_via_relay(relay, RelayRole::Sender).await,
+            None => TcpStream::connect(addr).await.map_err(|e| EelError::ConnectionError(e.to_string())),
         }
     }
 
     async fn handle_send_request(
         mut stream: TcpStream,
-        tx: UnboundedSender<AppEvent>,
+        join_addr: Option<SocketAddrV4>,
+        options: &SendOptions,
+        status: &mut Status,
         cancel_token: CancellationToken,
-        file_info: FileInfo,
-    ) {
-        tx.send(AppEvent::AppState(Handshake)).unwrap();
-        let _ = tx.send(AppEvent::Animate(Animation::Handshake));
-        let file_info_serialized = serde_json::to_string(&file_info).unwrap();
-        stream.write(file_info_serialized.as_bytes()).await.unwrap();
-        stream.write(b"\r\nITS OVER\r\n").await.unwrap();
+        transfer: CurrentTransfer,
+        mut file_info: FileInfo,
+    ) -> bool {
+        let tx = status.tx.clone();
+        let tuning = options.tuning;
+        let units = options.size_units;
+        status.go(Handshake);
+
+        let key = match &options.code {
+            Some(code) => match code.handshake(&mut stream, Side::Sender).await {
+                Ok(key) => {
+                    log!("Transfer code accepted by the receiver.");
+                    Some(key)
+                }
+                Err(e) => {
+                    log!(Warn: "{}", e);
+                    return false;
+                }
+            },
+            None => None,
+        };
+        let key = key.as_ref();
+
+        file_info.compression = vec![Compression::Lz4];
+        let file_info_serialized = match serde_json::to_string(&file_info) {
+            Ok(serialized) => serialized,
+            Err(e) => {
+                log!(Error: "Could not describe the file to the receiver: {}", e);
+                return false;
+            }
+        };
+        let offer = format!("{}\r\n{}", file_info_serialized, protocol::seal_line("ITS OVER", key, &format!("offer {}", file_info_serialized)));
+
+        if let Err(e) = stream.write_all(offer.as_bytes()).await {
+            log!(Error: "Could not send the file info, the receiver is gone: {}", e);
+            return false;
+        }
 
         let mut reader = BufReader::new(&mut stream);
         let mut response = String::new();
 
-        let response_result =
-            tokio::time::timeout(Duration::from_secs(10), reader.read_line(&mut response)).await;
+        if tokio::time::timeout(tuning.stall_timeout, reader.read_line(&mut response)).await.is_err() {
+            log!(Error: "Connection timeout elapsed! Aborting.");
+            return false;
+        }
 
-        match response_result {
-            Ok(_) => {
-                // log!(tx, "Received response: {}", response);
+        let acceptance = match response.trim_end() {
+            "NO, SIRE." => {
+                log!(Warn: "Remote EELFILE rejected the file for, as of now, vague reasons. Probably not enough space or file already exists.");
+                return false;
             }
-            Err(_) => {
-                log!(tx, "Connection timeout elapsed! Aborting.");
-                tx.send(AppEvent::AppState(Idle)).unwrap();
-                let _ = tx.send(AppEvent::Animate(IdleAfterError));
-                return;
+            line => match Acceptance::parse(line) {
+                Some(acceptance) => {
+                    log!(Debug: "Affirmative remote response received! Attempting to start transfer.");
+                    acceptance
+                }
+                None => {
+                    log!(Error: "The receiver hung up without answering. Aborting.");
+                    return false;
+                }
+            },
+        };
+
+        let mut connections = vec![stream];
+
+        if let (Some(session), Some(addr)) = (&acceptance.session, join_addr) {
+            // the receiver may hand out fewer connections than we offered, never more
+            let streams = acceptance.streams.min(file_info.streams).min(MAX_STREAMS);
+            if acceptance.streams > streams {
+                log!(Warn: "The receiver asked for {} connections, only opening the {} we offered.", acceptance.streams, streams);
+            }
+
+            for index in 1..streams {
+                match Self::join(addr, session, index).await {
+                    Ok(joined) => connections.push(joined),
+                    Err(e) => {
+                        log!(Error: "Could not open extra connection #{}: {}. Aborting.", index, e);
+                        return false;
+                    }
+                }
             }
+
+            log!(Debug: "Sending over {} parallel connections.", connections.len());
         }
-        
-        match response.as_str() {
-            "NO, SIRE." => {
-                log!(tx, "Remote EELFILE rejected the file for, as of now, vague reasons. Probably not enough space or file already exists.");
-                tx.send(AppEvent::AppState(Idle)).unwrap();
-                let _ = tx.send(AppEvent::Animate(IdleAfterError));
-                return;
-            } 
-            _ => {
-                log!(tx, "Affirmative remote response received! Attempting to start transfer.");
-                tx.send(AppEvent::AppState(Sending)).unwrap();
-                let _ = tx.send(AppEvent::Animate(Animation::Sending));
+
+        let Some(path) = file_info.path.clone() else {
+            log!(Error: "There's no file to send. Aborting.");
+            return false;
+        };
+
+        let kind = match Self::read_sample(&path).await {
+            Ok(sample) => protocol::choose_frame_kind(&acceptance.compression, &sample),
+            Err(e) => {
+                log!(Error: "Error reading file. Aborting connection. Error: {}", e);
+                return false;
+            }
+        };
+
+        if kind == FrameKind::Lz4 {
+            log!(Debug: "The file looks compressible, sending it lz4 compressed.");
+        }
+
+        status.go(Sending);
+
+        // hashed alongside the upload, it's needed for comparing with the receiver's copy at the end
+        let digest = tokio::spawn(protocol::file_digest(path.clone()));
+        let control = TransferControl::new(cancel_token.child_token());
+        transfer.lock().unwrap().replace(control.clone());
+
+        let progress = Arc::new(AtomicU64::new(0));
+        let mut sending = Box::pin(parallel::send_all(connections, path, file_info.size, kind, tuning, control.clone(), progress.clone()));
+        let started = Instant::now();
+        let result = Self::follow_progress(status, &mut sending, &control, &progress, file_info.size, Sending).await;
+        transfer.lock().unwrap().take();
+        drop(sending);
+
+        let (mut primary, wire_bytes) = match result {
+            Ok(sent) => sent,
+            Err(e) => {
+                match e.kind() {
+                    ErrorKind::Interrupted => {
+                        log!("Upload cancelled!");
+                    }
+                    ErrorKind::ConnectionAborted => {
+                        log!(Warn: "Upload stopped, {}.", e);
+                    }
+                    ErrorKind::TimedOut => {
+                        log!(Error: "Upload aborted: {}.", e);
+                    }
+                    _ => {
+                        log!(Error: "Connection to remote host closed unexpectedly. Aborting. Error: {}", e);
+                    }
+                }
+
+                return false;
+            }
+        };
+
+        let _ = tx.send(AppEvent::Progress(1.0));
+        Span::current().record("bytes", wire_bytes);
+        log!("All data sent at {}. {} went over the wire, compression ratio {:.2}:1. Waiting for the receiver to store it...",
+            Self::average_rate(file_info.size, started, units), Util::format_size(wire_bytes, units), protocol::compression_ratio(file_info.size, wire_bytes));
+
+        let our_digest = match digest.await.map_err(Error::other).and_then(|digest| digest) {
+            Ok(digest) => digest,
+            Err(e) => {
+                log!(Error: "Could not hash the file to check the receiver's copy: {}", e);
+                return false;
+            }
+        };
+
+        // the receiver still has to fsync and hash everything, so this gets more time the bigger the file
+        let wait = tuning.stall_timeout + Duration::from_secs(file_info.size / (20 * 1024 * 1024));
+        let mut reply = String::new();
+        let outcome = match tokio::time::timeout(wait, BufReader::new(&mut primary).read_line(&mut reply)).await {
+            Ok(Ok(_)) => protocol::open_line(&reply, key, "outcome").and_then(Outcome::parse),
+            _ => None,
+        };
+
+        match outcome {
+            Some(Outcome::Stored(their_digest)) if their_digest == our_digest => {
+                let verdict = protocol::seal_line(protocol::VERIFIED, key, &format!("verdict {}", their_digest));
+
+                // without the verdict the receiver throws its copy away
+                if let Err(e) = tuning.or_stall(primary.write_all(verdict.as_bytes())).await {
+                    log!(Error: "The checksums match, but the receiver couldn't be told: {}", e);
+                    return false;
+                }
+
+                log!(Success: "Upload complete. The receiver stored the file and the checksums match.");
+                true
+            }
+            Some(Outcome::Stored(their_digest)) => {
+                let verdict = protocol::seal_line(protocol::MISMATCH, key, &format!("verdict {}", their_digest));
+                let _ = primary.write_all(verdict.as_bytes()).await;
+                log!(Error: "The receiver's copy doesn't match the original, it has been told to throw it away.");
+                false
+            }
+            Some(Outcome::Failed(reason)) => {
+                log!(Error: "The receiver could not store the file: {}", reason);
+                false
+            }
+            None => {
+                log!(Warn: "The receiver never confirmed it stored the file, assuming it didn't.");
+                false
             }
         }
+    }
 
-        let mut remaining_size = file_info.size;
-        let mut buffer = vec![0u8; 64 * 1024];
-        let mut file = File::open(file_info.path.as_ref().unwrap()).await.unwrap();
+    // pauses included, it's what the user actually waited
+    fn average_rate(size: u64, started: Instant, units: SizeUnits) -> String {
+        Util::format_rate(size as f64 / started.elapsed().as_secs_f64().max(0.001), units)
+    }
+
+    // reports progress until the transfer is done, and flips between `state` and Paused whenever either end pauses it
+    async fn follow_progress<T>(
+        status: &mut Status,
+        transfer: &mut (impl Future<Output = Result<T, Error>> + Unpin),
+        control: &TransferControl,
+        progress: &AtomicU64,
+        size: u64,
+        state: AppState,
+    ) -> Result<T, Error> {
+        let tx = status.tx.clone();
+        let peer = match state {
+            Sending => "receiver",
+            _ => "sender",
+        };
 
-        while remaining_size > 0 {
+        let mut progress_timer = tokio::time::interval(PROGRESS_INTERVAL);
+        let (mut paused_here, mut paused_by_peer) = (false, false);
+
+        let result = loop {
             select! {
-                _ = cancel_token.cancelled() => {
-                    log!(tx, "Upload cancelled!");
-                    break;
+                _ = progress_timer.tick() => {
+                    let _ = tx.send(AppEvent::Progress(Self::fraction(progress.load(Ordering::Relaxed), size)));
                 }
 
-                read = file.read(&mut buffer) => {
-                    if let Err(e) = read {
-                        log!(tx, "Error reading file. Aborting connection. Error: {}", e);
-                        tx.send(AppEvent::AppState(Idle)).unwrap();
-                        let _ = tx.send(AppEvent::Animate(IdleAfterError));
-                        break;
-                    }
+                result = &mut *transfer => break result,
+            }
 
-                    let bytes = read.unwrap();
-                    if bytes == 0 {
-                        tx.send(AppEvent::AppState(Idle)).unwrap();
-                        let _ = tx.send(AppEvent::Animate(IdleAfterError));
-                        log!(tx, "Funny EOF error. This should never happen (it always does when I write this.");
-                    }
+            let was_paused = paused_here || paused_by_peer;
 
-                    let to_write = std::cmp::min(bytes as u64, remaining_size) as usize;
-                    
-                    // todo: if the other end drops connection this freezes as it waits
-                    let write_result = stream.write_all(&buffer[..to_write]).await;
+            if control.is_paused_here() != paused_here {
+                paused_here = !paused_here;
+                log!("Transfer {}.", if paused_here { "paused" } else { "resumed" });
+            }
 
-                    if let Err(e) = write_result {
-                        tx.send(AppEvent::AppState(Idle)).unwrap();
-                        let _ = tx.send(AppEvent::Animate(IdleAfterError));
-                        log!(tx, "Connection to remote host closed unexpectedly. Aborting. Error: {}", e);
-                        break;
-                    }
+            if control.is_paused_by_peer() != paused_by_peer {
+                paused_by_peer = !paused_by_peer;
+                log!("The {} {} the transfer.", peer, if paused_by_peer { "paused" } else { "resumed" });
+            }
 
-                    remaining_size -= to_write as u64;
-                    tx.send(AppEvent::Progress(1.0 - (remaining_size as f32 / file_info.size as f32))).unwrap();
+            match (was_paused, paused_here || paused_by_peer) {
+                (false, true) => status.go(Paused),
+                (true, false) => status.go(state.clone()),
+                _ => {}
             }
+        };
+
+        if paused_here || paused_by_peer {
+            status.go(state);
+        }
+
+        result
+    }
+
+    async fn join(addr: SocketAddrV4, session: &str, index: u8) -> Result<TcpStream, Error> {
+        let mut stream = TcpStream::connect(addr).await?;
+        parallel::write_join(&mut stream, session, index).await?;
+        Ok(stream)
+    }
+
+    // the start of the file decides whether compressing it is worth it
+    async fn read_sample(path: &Path) -> Result<Vec<u8>, Error> {
+        let mut sample = Vec::new();
+        File::open(path).await?.take(CHUNK_SIZE as u64).read_to_end(&mut sample).await?;
+        Ok(sample)
+    }
+
+    fn fraction(done: u64, total: u64) -> f32 {
+        if total == 0 {
+            1.0
+        } else {
+            done as f32 / total as f32
         }
     }
+
+    // just the last part of whatever the sender called the file, None for names that try to go anywhere else
+    fn safe_file_name(name: &str) -> Option<String> {
+        let path = Path::new(name);
+
+        if name.is_empty() || path.is_absolute() || path.has_root() || path.components().any(|part| part == std::path::Component::ParentDir) {
+            return None;
+        }
+
+        path.file_name().map(|name| name.to_string_lossy().into_owned())
+    }
+
+    // where the incoming file goes given what's already in the folder, None when it should be refused
+    fn resolve_conflict(path: PathBuf, policy: ConflictPolicy) -> Option<PathBuf> {
+        if !path.exists() {
+            return Some(path);
+        }
+
+        match policy {
+            ConflictPolicy::Refuse => None,
+            ConflictPolicy::Overwrite => Some(path),
+            ConflictPolicy::Rename => {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+                let extension = path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+
+                (1..)
+                    .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+                    .find(|candidate| !candidate.exists())
+            }
+        }
     }
 
     async fn create_file(file_info: FileInfo) -> Result<File, Error> {
-        if let None = file_info.path {
+        if file_info.path.is_none() {
             return Err(Error::new(
                 ErrorKind::InvalidData,
                 "The save directory is missing from the passed data!",
             ));
         };
 
-        File::options()
-            .read(true)
-            .append(true)
+        // preallocated, parallel streams write their ranges at their own offsets
+        let file = File::options()
+            .write(true)
             .create_new(true)
             .open(file_info.path.as_ref().unwrap())
-            .await
+            .await?;
+
+        file.set_len(file_info.size).await?;
+        Ok(file)
     }
 
     // super hacky solution that just assumes the user won't type in a relative path
     // I wish I could test it on some other device but oh well
     // also it will just give me a false even if there's an error or there are no matching drives
     // this is also not portable to other OSs, not that I care
-    fn is_enough_space(path: &PathBuf, filesize: u64) -> bool {
+    fn is_enough_space(path: &Path, filesize: u64) -> bool {
         let mut volume = path.to_str().unwrap()[0..2].to_string();
-        volume.push_str("\\");
+        volume.push('\\');
 
         let disks = Disks::new_with_refreshed_list();
 
@@ -451,8 +1112,13 @@ impl NetController {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use eel_file::Animation;
+    use eel_file::eel_log::EelWatcher;
+    use eel_file::log_layer::{self, WatcherLayer};
+    use std::sync::OnceLock;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_free_space_check() {
         let file_size: u64 = 1024 * 1024 * 1024 * 150;
         let path_buf = PathBuf::from("C:/Users/user/RustroverProjects/eel_file/assets");
@@ -467,4 +1133,279 @@ mod tests {
 
         assert_eq!(result, true);
     }
-}
\ No newline at end of file
+
+    // every test's log in one place, it has to be set up before the task it's watching starts
+    fn test_log() -> Arc<Mutex<EelWatcher>> {
+        static LOG: OnceLock<Arc<Mutex<EelWatcher>>> = OnceLock::new();
+
+        LOG.get_or_init(|| {
+            let watcher = Arc::new(Mutex::new(EelWatcher::new()));
+            log_layer::install(WatcherLayer::new(watcher.clone()), false).unwrap();
+            watcher
+        })
+        .clone()
+    }
+
+    // plays back what a task told the UI through a state machine of our own, so an illegal change fails the test
+    fn replay(mut rx: UnboundedReceiver<AppEvent>) -> (Vec<AppState>, Animation) {
+        let mut machine = StateMachine::new();
+        let mut states = Vec::new();
+        let mut animation = None;
+
+        // the ones the task's own machine refused never made it into `rx`
+        for record in test_log().lock().unwrap().records() {
+            assert!(!record.message.starts_with("Illegal state change"), "{}", record.message);
+        }
+
+        while let Ok(event) = rx.try_recv() {
+            match event {
+                AppEvent::AppState(state) => {
+                    machine.go(state.clone()).unwrap();
+                    states.push(state);
+                }
+                AppEvent::Animate(animate) => animation = Some(animate),
+                _ => {}
+            }
+        }
+
+        (states, animation.unwrap())
+    }
+
+    async fn free_port() -> u16 {
+        TcpListener::bind("0.0.0.0:0").await.unwrap().local_addr().unwrap().port()
+    }
+
+    // accepts one connection, reads the offer and answers it with `reply`, an empty one just hangs up
+    async fn fake_receiver(reply: &'static str) -> SocketAddrV4 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let _ = NetController::read_offer(&mut stream, None).await;
+            let _ = stream.write_all(reply.as_bytes()).await;
+        });
+
+        SocketAddrV4::new(std::net::Ipv4Addr::LOCALHOST, port)
+    }
+
+    async fn send_to(addr: SocketAddrV4, token: CancellationToken, options: SendOptions) -> (Vec<AppState>, Animation) {
+        test_log();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let file_info = FileInfo {
+            path: Some(std::env::temp_dir().join(format!("eel_missing_{}", parallel::new_session()))),
+            size: 10,
+            name: "missing.bin".to_string(),
+            sender_addr: None,
+            compression: Vec::new(),
+            streams: 1,
+        };
+
+        NetController::send(tx, token, Arc::new(Mutex::new(None)), addr, file_info, options).await;
+        replay(rx)
+    }
+
+    #[tokio::test]
+    async fn test_failed_sends_end_idle_after_error() {
+        let refused = SocketAddrV4::new(std::net::Ipv4Addr::LOCALHOST, free_port().await);
+        let (states, animation) = send_to(refused, CancellationToken::new(), SendOptions::default()).await;
+        assert_eq!(states, vec![Connecting, Idle]);
+        assert!(animation == Animation::IdleAfterError);
+
+        // rejected, hung up on, answered with nonsense, and accepted for a file that isn't there anymore
+        for reply in ["NO, SIRE.\r\n", "", "WHAT?\r\n", "HAND IT OVER\r\n"] {
+            let addr = fake_receiver(reply).await;
+            let (states, animation) = send_to(addr, CancellationToken::new(), SendOptions::default()).await;
+            assert_eq!(states, vec![Connecting, Handshake, Idle], "{:?}", reply);
+            assert!(animation == Animation::IdleAfterError, "{:?}", reply);
+        }
+
+        // a relay that never pairs us, cancelled while still connecting
+        let silent = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay = RelayConfig {
+            addr: match silent.local_addr().unwrap() {
+                SocketAddr::V4(addr) => addr,
+                _ => unreachable!(),
+            },
+            session: "nobody".to_string(),
+        };
+        let options = SendOptions { relay: Some(relay.clone()), ..SendOptions::default() };
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancel.cancel();
+        });
+        let (states, animation) = send_to(relay.addr, token, options).await;
+        assert_eq!(states, vec![Connecting, Idle]);
+        assert!(animation == Animation::Idle);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_acceptance_is_clamped() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = SocketAddrV4::new(std::net::Ipv4Addr::LOCALHOST, listener.local_addr().unwrap().port());
+
+        // asks for way more connections than were offered, then counts how many turn up
+        let receiver = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let _ = NetController::read_offer(&mut stream, None).await;
+            stream.write_all(b"HAND IT OVER streams=200 session=eels\r\n").await.unwrap();
+
+            let mut joins = Vec::new();
+            while let Ok(Ok((joined, _))) = tokio::time::timeout(Duration::from_millis(500), listener.accept()).await {
+                joins.push(joined);
+            }
+            joins.len()
+        });
+
+        let options = SendOptions { streams: 3, ..SendOptions::default() };
+        send_to(addr, CancellationToken::new(), options).await;
+        assert_eq!(receiver.await.unwrap(), 2);
+    }
+
+    #[test]
+    fn test_name_conflicts() {
+        let dir = std::env::temp_dir().join(format!("eel_conflict_{}", parallel::new_session()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let taken = dir.join("eel.tar.gz");
+        std::fs::write(&taken, b"old").unwrap();
+        std::fs::write(dir.join("eel.tar (1).gz"), b"older").unwrap();
+
+        let fresh = dir.join("new.bin");
+        assert_eq!(NetController::resolve_conflict(fresh.clone(), ConflictPolicy::Refuse), Some(fresh));
+        assert_eq!(NetController::resolve_conflict(taken.clone(), ConflictPolicy::Refuse), None);
+        assert_eq!(NetController::resolve_conflict(taken.clone(), ConflictPolicy::Overwrite), Some(taken.clone()));
+        assert_eq!(NetController::resolve_conflict(taken, ConflictPolicy::Rename), Some(dir.join("eel.tar (2).gz")));
+
+        assert_eq!(NetController::safe_file_name("eel.tar.gz").as_deref(), Some("eel.tar.gz"));
+        assert_eq!(NetController::safe_file_name("sub/dir/eel.bin").as_deref(), Some("eel.bin"));
+        for bad in ["", "..", "../eel.bin", "sub/../../eel.bin", "/etc/passwd", "."] {
+            assert_eq!(NetController::safe_file_name(bad), None, "{}", bad);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_listener_survives_bad_offers() {
+        test_log();
+        let occupied = TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let port = occupied.local_addr().unwrap().port();
+        NetController::listen(tx, CancellationToken::new(), Arc::new(Mutex::new(None)), Arc::new(Mutex::new(None)), std::env::temp_dir(), port, ListenOptions::default()).await;
+        let (states, animation) = replay(rx);
+        assert_eq!(states, vec![Idle]);
+        assert!(animation == Animation::IdleAfterError);
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let port = free_port().await;
+        let server_token = CancellationToken::new();
+        let listening = tokio::spawn(NetController::listen(tx, server_token.clone(), Arc::new(Mutex::new(None)), Arc::new(Mutex::new(None)), std::env::temp_dir(), port, ListenOptions::default()));
+
+        let too_big = FileInfo {
+            path: None,
+            size: u64::MAX / 2,
+            name: format!("eel_too_big_{}", parallel::new_session()),
+            sender_addr: None,
+            compression: Vec::new(),
+            streams: 1,
+        };
+        let offers = ["nonsense\r\nITS OVER\r\n".to_string(), format!("{}\r\nITS OVER\r\n", serde_json::to_string(&too_big).unwrap())];
+
+        for offer in offers {
+            let mut stream = loop {
+                match TcpStream::connect(("127.0.0.1", port)).await {
+                    Ok(stream) => break stream,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+                }
+            };
+            stream.write_all(offer.as_bytes()).await.unwrap();
+            // the listener hangs up once it's done with us
+            let _ = stream.read_to_end(&mut Vec::new()).await;
+        }
+
+        server_token.cancel();
+        listening.await.unwrap();
+
+        let (states, animation) = replay(rx);
+        assert_eq!(states, vec![Listening, Handshake, Listening, Handshake, Listening, Idle]);
+        assert!(animation == Animation::Idle);
+    }
+
+    #[tokio::test]
+    async fn test_only_verified_files_are_kept() {
+        test_log();
+
+        for (verdict, kept) in [(Some(protocol::VERIFIED), true), (Some(protocol::MISMATCH), false), (None, false)] {
+            let path = std::env::temp_dir().join(format!("eel_verdict_{}", parallel::new_session()));
+            std::fs::write(&path, b"eels").unwrap();
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let connecting = TcpStream::connect(listener.local_addr().unwrap());
+            let (accepted, sender) = join!(listener.accept(), connecting);
+            let mut sender = BufReader::new(sender.unwrap());
+
+            let sender = tokio::spawn(async move {
+                let mut outcome = String::new();
+                sender.read_line(&mut outcome).await.unwrap();
+                assert!(outcome.starts_with("STORED OK "));
+
+                // no verdict is the sender hanging up right after reading the digest
+                if let Some(verdict) = verdict {
+                    sender.get_mut().write_all(format!("{}\r\n", verdict).as_bytes()).await.unwrap();
+                }
+            });
+
+            let stored = NetController::confirm_stored(accepted.unwrap().0, &path, Tuning::default(), None).await;
+            sender.await.unwrap();
+
+            assert_eq!((stored, path.exists()), (kept, kept));
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[tokio::test]
+    #[cfg_attr(not(windows), ignore = "the free space check only knows Windows drives")]
+    async fn test_coded_overwrite_lands_only_once_verified() {
+        test_log();
+        let dir = std::env::temp_dir().join(format!("eel_overwrite_{}", parallel::new_session()));
+        std::fs::create_dir_all(dir.join("out")).unwrap();
+        std::fs::write(dir.join("out").join("eel.bin"), b"old eel").unwrap();
+        let content: Vec<u8> = (0..300_000).map(|i| (i % 251) as u8).collect();
+        std::fs::write(dir.join("eel.bin"), &content).unwrap();
+
+        let code: TransferCode = "7-eel-moray-kelp-tide".parse().unwrap();
+        let port = free_port().await;
+        let listen_options = ListenOptions {
+            code: Some(code.clone()),
+            conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        let (tx, _rx) = mpsc::unbounded_channel();
+        // a code makes the listener one-shot, it returns once the transfer's done
+        let listening = tokio::spawn(NetController::listen(tx, CancellationToken::new(), Arc::new(Mutex::new(None)), Arc::new(Mutex::new(None)), dir.join("out"), port, listen_options));
+
+        let file_info = FileInfo {
+            path: Some(dir.join("eel.bin")),
+            size: content.len() as u64,
+            name: "somewhere/else/eel.bin".to_string(),
+            sender_addr: None,
+            compression: Vec::new(),
+            streams: 1,
+        };
+        let options = SendOptions { code: Some(code), ..Default::default() };
+        let addr = SocketAddrV4::new(std::net::Ipv4Addr::LOCALHOST, port);
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        NetController::send(tx, CancellationToken::new(), Arc::new(Mutex::new(None)), addr, file_info, options).await;
+        listening.await.unwrap();
+
+        // only the name was kept, and nothing but the finished file is left in the folder
+        let (_, animation) = replay(rx);
+        assert!(animation == Animation::IdleAfterSuccess);
+        assert_eq!(std::fs::read(dir.join("out").join("eel.bin")).unwrap(), content);
+        assert_eq!(std::fs::read_dir(dir.join("out")).unwrap().count(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,51 @@
+pub static NORMAL_FACTS: &[&str] = &[
+    "Moray eels have a second set of jaws called pharyngeal jaws.",
+    "Eels are actually fish, despite their snake-like appearance.",
+    "Some freshwater eels migrate thousands of kilometers to spawn in the ocean.",
+    "The European eel spawns in the Sargasso Sea.",
+    "Juvenile eels are called \"elvers.\"",
+    "Electric eels are not true eels; they belong to the knifefish family. This does not mean I won't pad this list with electric eel facts.",
+    "Some eels can live up to 85 years in captivity.",
+    "Eels undergo several life stages: leptocephalus, elver, yellow eel, silver eel.",
+    "American and European eels both spawn in the Sargasso Sea.",
+    "Eels can absorb oxygen through their skin, allowing them to survive short periods out of water.",
+    "The Japanese eel is a delicacy in sushi and grilled dishes.",
+    "Eels have a strong sense of smell and poor eyesight.",
+    "Moray eels secrete mucus over their scaleless skin for protection.",
+    "The gulper eel has a mouth larger than its body.",
+    "Eels are nocturnal hunters.",
+    "Eel blood is toxic to humans if not cooked.",
+    "Some species of eels can swim backward.",
+    "Eels do not breed in captivity easily.",
+    "The longest eel species can reach over 13 feet in length.",
+    "Conger eels are common in European coastal waters.",
+    "Some eels have been seen using tools.",
+    "Eel skin has been used to make durable leather products.",
+    "Electric eels use shocks for both hunting and navigation.",
+    "Eels are catadromous—living in freshwater but spawning in saltwater.",
+    "Scientists didn’t confirm eel spawning grounds until the 20th century.",
+    "Moray eels often cooperate with groupers when hunting.",
+    "Eels can generate shocks repeatedly without rest.",
+    "Baby eels are transparent.",
+    "Some cultures consider eels sacred or symbolic of ancestors.",
+    "Eels communicate through low-frequency sounds.",
+    "Electric eels have three pairs of abdominal organs that generate electricity.",
+    "Eel populations are declining due to overfishing and habitat loss.",
+    "Some eels can survive freezing temperatures.",
+    "Moray eels’ pharyngeal jaws shoot forward like the alien from Alien.",
+    "Conger eels can weigh over 100 lbs.",
+    "Eels swim in an S-shaped motion.",
+    "European eels have been known to travel over 5,000 km to spawn.",
+    "Eels produce slime as a defense mechanism.",
+    "Eels are not kosher according to Jewish dietary laws.",
+    "Some eels build burrows in sandy or muddy bottoms.",
+    "Japanese folklore includes shapeshifting eels.",
+    "Aristotle thought eels spontaneously generated from mud.",
+    "Leptocephalus larvae look like clear willow leaves.",
+    "Eels are attracted to light and electrical fields.",
+    "Eel mucus contains antimicrobial properties.",
+    "Eels are very flexible and can tie themselves in knots.",
+    "The electric eel is the only species that can generate a strong electric shock.",
+    "Eel migration is still not completely understood.",
+    "Eels are incredibly hard to keep in aquariums due to their escape artistry.",
+];
\ No newline at end of file
@@ -0,0 +1,274 @@
+use crate::transfer_queue::JobId;
+use eel_file::settings::NotificationSettings;
+use eel_file::{FileInfo, SizeUnits, Util};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+#[cfg(not(windows))]
+const APP_NAME: &str = "EELFILE";
+// at most one of each kind this often, the ones in between get counted into the next
+const RATE_LIMIT: Duration = Duration::from_secs(10);
+// servers that never report a notification closed would keep us waiting on its button forever otherwise
+#[cfg(all(unix, not(target_os = "macos")))]
+const ACTION_WAIT: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NotificationKind {
+    Offer,
+    Complete,
+    Failed,
+}
+
+// what a notification's buttons ask the app for, opening a folder is handled right here instead
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationAction {
+    // clicking the notification itself does this too
+    Show,
+    Abort,
+    Retry(JobId),
+}
+
+type OnAction = Arc<dyn Fn(NotificationAction) + Send + Sync>;
+
+// Desktop notifications for when the window isn't the one being looked at. Each kind can be turned off
+// and is rate limited on its own, so a burst of failed offers doesn't bury everything else.
+pub struct Notifier {
+    settings: NotificationSettings,
+    size_units: SizeUnits,
+    background: bool,
+    last_shown: HashMap<NotificationKind, Instant>,
+    suppressed: HashMap<NotificationKind, u32>,
+    // one thread shows them all, a slow or missing notification server only ever holds up that one
+    toasts: UnboundedSender<Toast>,
+}
+
+struct Toast {
+    summary: String,
+    body: String,
+    #[cfg_attr(target_os = "macos", allow(dead_code))]
+    buttons: Vec<Button>,
+}
+
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+enum Button {
+    OpenFolder(PathBuf),
+    App(NotificationAction),
+}
+
+impl Button {
+    #[cfg_attr(target_os = "macos", allow(dead_code))]
+    fn label(&self) -> &'static str {
+        match self {
+            Button::OpenFolder(_) => "Open folder",
+            Button::App(NotificationAction::Show) => "Show",
+            Button::App(NotificationAction::Abort) => "Abort",
+            Button::App(NotificationAction::Retry(_)) => "Retry",
+        }
+    }
+}
+
+impl Notifier {
+    // `on_action` gets called from the notification thread with whatever button was pressed
+    pub fn new(settings: NotificationSettings, size_units: SizeUnits, on_action: impl Fn(NotificationAction) + Send + Sync + 'static) -> Notifier {
+        let (toasts, queue) = mpsc::unbounded_channel();
+        let on_action: OnAction = Arc::new(on_action);
+        std::thread::spawn(move || Self::show_all(queue, on_action));
+
+        Notifier {
+            settings,
+            size_units,
+            background: false,
+            last_shown: HashMap::new(),
+            suppressed: HashMap::new(),
+            toasts,
+        }
+    }
+
+    pub fn configure(&mut self, settings: NotificationSettings, size_units: SizeUnits) {
+        self.settings = settings;
+        self.size_units = size_units;
+    }
+
+    // nobody needs a notification about the window they're looking at
+    pub fn set_background(&mut self, background: bool) {
+        self.background = background;
+    }
+
+    pub fn offer(&mut self, file: &FileInfo) {
+        let from = file.sender_addr.map(|addr| format!(" from {}", addr.ip())).unwrap_or_default();
+        let buttons = vec![Button::App(NotificationAction::Show), Button::App(NotificationAction::Abort)];
+        self.notify(NotificationKind::Offer, "Incoming file".to_string(), format!("{}, {}{}", file.name, Util::format_size(file.size, self.size_units), from), buttons);
+    }
+
+    pub fn received(&mut self, file: &FileInfo) {
+        let folder = file.path.as_deref().and_then(Path::parent).map(Path::to_path_buf);
+        let place = folder.as_ref().map(|folder| format!(", saved to {}", folder.display())).unwrap_or_default();
+        let buttons = folder.into_iter().map(Button::OpenFolder).chain([Button::App(NotificationAction::Show)]).collect();
+        self.notify(NotificationKind::Complete, format!("Received {}", file.name), format!("{}{}", Util::format_size(file.size, self.size_units), place), buttons);
+    }
+
+    pub fn receive_failed(&mut self, file: &FileInfo) {
+        self.notify(NotificationKind::Failed, format!("Receiving {} failed", file.name), "The log says what went wrong.".to_string(), vec![Button::App(NotificationAction::Show)]);
+    }
+
+    pub fn sent(&mut self, file: &FileInfo) {
+        self.notify(NotificationKind::Complete, format!("Sent {}", file.name), "The receiver has a matching copy.".to_string(), vec![Button::App(NotificationAction::Show)]);
+    }
+
+    pub fn send_failed(&mut self, id: JobId, file: &FileInfo) {
+        let buttons = vec![Button::App(NotificationAction::Retry(id)), Button::App(NotificationAction::Show)];
+        self.notify(NotificationKind::Failed, format!("Sending {} failed", file.name), "It can be retried from the queue.".to_string(), buttons);
+    }
+
+    fn notify(&mut self, kind: NotificationKind, summary: String, body: String, buttons: Vec<Button>) {
+        if !self.background || !self.enabled(kind) {
+            return;
+        }
+
+        let Some(skipped) = self.allow(kind, Instant::now()) else { return };
+        let summary = match skipped {
+            0 => summary,
+            skipped => format!("{} (and {} more)", summary, skipped),
+        };
+
+        let _ = self.toasts.send(Toast { summary, body, buttons });
+    }
+
+    fn enabled(&self, kind: NotificationKind) -> bool {
+        match kind {
+            NotificationKind::Offer => self.settings.offer,
+            NotificationKind::Complete => self.settings.complete,
+            NotificationKind::Failed => self.settings.failed,
+        }
+    }
+
+    // Some(how many were held back since the last one) when this one can be shown
+    fn allow(&mut self, kind: NotificationKind, now: Instant) -> Option<u32> {
+        if let Some(last) = self.last_shown.get(&kind)
+            && now.duration_since(*last) < RATE_LIMIT
+        {
+            *self.suppressed.entry(kind).or_default() += 1;
+            return None;
+        }
+
+        self.last_shown.insert(kind, now);
+        Some(self.suppressed.remove(&kind).unwrap_or_default())
+    }
+
+    // runs until the Notifier is gone, the waits for a button press happen side by side on the same thread
+    fn show_all(mut queue: UnboundedReceiver<Toast>, on_action: OnAction) {
+        let Ok(runtime) = tokio::runtime::Builder::new_current_thread().enable_time().build() else { return };
+        let local = tokio::task::LocalSet::new();
+
+        local.block_on(&runtime, async {
+            while let Some(toast) = queue.recv().await {
+                Self::show(toast, &on_action).await;
+            }
+        });
+    }
+
+    #[cfg_attr(target_os = "macos", allow(dead_code))]
+    fn press(button: &Button, on_action: &OnAction) {
+        match button {
+            #[cfg(windows)]
+            Button::OpenFolder(folder) => {
+                let _ = std::process::Command::new("explorer").arg(folder).spawn();
+            }
+            #[cfg(not(windows))]
+            Button::OpenFolder(folder) => {
+                let _ = std::process::Command::new("xdg-open").arg(folder).spawn();
+            }
+            Button::App(action) => on_action(*action),
+        }
+    }
+
+    // the buttons are told apart by their index, clicking the notification itself counts as Show
+    #[cfg(all(unix, not(target_os = "macos")))]
+    async fn show(toast: Toast, on_action: &OnAction) {
+        use notify_rust::{ActionResponse, Notification};
+
+        let mut notification = Notification::new();
+        notification.appname(APP_NAME).summary(&toast.summary).body(&toast.body).action("default", "Show");
+
+        for (index, button) in toast.buttons.iter().enumerate() {
+            notification.action(&index.to_string(), button.label());
+        }
+
+        let Ok(handle) = notification.show_async().await else { return };
+        let on_action = on_action.clone();
+
+        tokio::task::spawn_local(async move {
+            let mut pressed = None;
+            let wait = handle.wait_for_action_async(|action| {
+                pressed = match action {
+                    ActionResponse::Custom("default") => Some(None),
+                    ActionResponse::Custom(key) => key.parse::<usize>().ok().map(Some),
+                    _ => None,
+                }
+            });
+
+            if tokio::time::timeout(ACTION_WAIT, wait).await.is_err() {
+                handle.close_async().await;
+                return;
+            }
+
+            match pressed.map(|index| index.and_then(|index| toast.buttons.get(index))) {
+                Some(Some(button)) => Self::press(button, &on_action),
+                Some(None) => on_action(NotificationAction::Show),
+                None => {}
+            }
+        });
+    }
+
+    // notify-rust leaves the buttons out on Windows, so the toast is built here instead
+    #[cfg(windows)]
+    async fn show(toast: Toast, on_action: &OnAction) {
+        use tauri_winrt_notification::Toast as WindowsToast;
+
+        let mut notification = WindowsToast::new(WindowsToast::POWERSHELL_APP_ID).title(&toast.summary).text2(&toast.body);
+
+        for (index, button) in toast.buttons.iter().enumerate() {
+            notification = notification.add_button(button.label(), &index.to_string());
+        }
+
+        let on_action = on_action.clone();
+        let _ = notification
+            .on_activated(move |pressed| {
+                match pressed.and_then(|key| key.parse::<usize>().ok()).and_then(|index| toast.buttons.get(index)) {
+                    Some(button) => Self::press(button, &on_action),
+                    None => on_action(NotificationAction::Show),
+                }
+                Ok(())
+            })
+            .show();
+    }
+
+    // macOS notifications have no buttons through notify-rust, there it's just the message
+    #[cfg(target_os = "macos")]
+    async fn show(toast: Toast, _on_action: &OnAction) {
+        let _ = notify_rust::Notification::new().appname(APP_NAME).summary(&toast.summary).body(&toast.body).show();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_counts_what_it_held_back() {
+        let mut notifier = Notifier::new(NotificationSettings::default(), SizeUnits::default(), |_| {});
+        let start = Instant::now();
+
+        assert_eq!(notifier.allow(NotificationKind::Failed, start), Some(0));
+        assert_eq!(notifier.allow(NotificationKind::Failed, start + Duration::from_secs(1)), None);
+        assert_eq!(notifier.allow(NotificationKind::Failed, start + Duration::from_secs(2)), None);
+        // other kinds have their own limit
+        assert_eq!(notifier.allow(NotificationKind::Complete, start + Duration::from_secs(2)), Some(0));
+
+        assert_eq!(notifier.allow(NotificationKind::Failed, start + RATE_LIMIT), Some(2));
+        assert_eq!(notifier.allow(NotificationKind::Failed, start + RATE_LIMIT * 3), Some(0));
+    }
+}
@@ -0,0 +1,968 @@
+use crate::control::{cancelled_by_peer, cancelled_here, TransferControl, CANCEL_GRACE};
+use crate::protocol::{Frame, FrameKind, FRAME_HEADER_LEN, MAX_CHUNK_SIZE};
+#[cfg(target_os = "linux")]
+use crate::zero_copy;
+use eel_file::settings::Settings;
+use std::io::{Error, ErrorKind, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::select;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinSet;
+use tokio::time::{timeout_at, Instant, Interval, MissedTickBehavior};
+
+// more than this mostly just fights with itself for the same bandwidth
+pub const MAX_STREAMS: u8 = 8;
+
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+const JOIN_MAGIC: &[u8] = b"EEL JOIN";
+
+// a peer that goes quiet for this long mid-transfer is treated as gone
+pub const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+// well under what home routers give an idle TCP mapping before forgetting it
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+type RangeTasks = JoinSet<Result<(usize, TcpStream, u64), Error>>;
+
+// knobs for the data phase, the defaults are what the app uses
+#[derive(Clone, Copy, Debug)]
+pub struct Tuning {
+    // bytes of the file per frame, bigger frames mean fewer syscalls and headers
+    pub chunk_size: usize,
+    // sendfile/splice for uncompressed frames where the OS has them, lz4 frames always take the normal path
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub zero_copy: bool,
+    // how long a single frame may take to go out or come in before the transfer counts as stalled
+    pub stall_timeout: Duration,
+    // how often a paused connection says something so NATs and firewalls don't drop it
+    pub keepalive_interval: Duration,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            chunk_size: 1024 * 1024,
+            zero_copy: true,
+            stall_timeout: STALL_TIMEOUT,
+            keepalive_interval: KEEPALIVE_INTERVAL,
+        }
+    }
+}
+
+impl Tuning {
+    pub fn from_settings(settings: &Settings) -> Tuning {
+        Tuning {
+            chunk_size: settings.chunk_size_kib as usize * 1024,
+            stall_timeout: Duration::from_secs(settings.stall_timeout_secs),
+            keepalive_interval: Duration::from_secs(settings.keepalive_secs),
+            ..Tuning::default()
+        }
+    }
+
+    fn chunk_size(&self) -> usize {
+        self.chunk_size.clamp(1, MAX_CHUNK_SIZE)
+    }
+
+    // gives up on `io` with a TimedOut error if nothing comes of it within the stall timeout
+    pub async fn or_stall<T>(&self, io: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+        match tokio::time::timeout(self.stall_timeout, io).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::new(
+                ErrorKind::TimedOut,
+                format!("the transfer stalled, nothing moved for {} seconds", self.stall_timeout.as_secs()),
+            )),
+        }
+    }
+}
+
+// contiguous byte ranges, one per stream. The receiver computes the same split so they never go on the wire
+pub fn split_ranges(size: u64, streams: u8) -> Vec<Range<u64>> {
+    let streams = streams.max(1) as u64;
+
+    (0..streams)
+        .map(|i| size * i / streams..size * (i + 1) / streams)
+        .collect()
+}
+
+pub fn new_session() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+// the first thing an extra connection says: magic, session and which range it is going to carry
+pub async fn write_join(stream: &mut TcpStream, session: &str, index: u8) -> Result<(), Error> {
+    stream.write_all(JOIN_MAGIC).await?;
+    stream.write_u8(session.len() as u8).await?;
+    stream.write_all(session.as_bytes()).await?;
+    stream.write_u8(index).await
+}
+
+async fn read_join(stream: &mut TcpStream) -> Result<(String, u8), Error> {
+    let mut magic = [0u8; JOIN_MAGIC.len()];
+    stream.read_exact(&mut magic).await?;
+
+    if magic != JOIN_MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "not a join request"));
+    }
+
+    let mut session = vec![0u8; stream.read_u8().await? as usize];
+    stream.read_exact(&mut session).await?;
+    let index = stream.read_u8().await?;
+
+    Ok((String::from_utf8_lossy(&session).to_string(), index))
+}
+
+// Waits for the sender's extra connections. Anything that doesn't know the session, or claims a range
+// that's already taken, gets dropped. Returns every connection sorted by range, the primary one first.
+pub async fn accept_joins(
+    listener: &TcpListener,
+    primary: TcpStream,
+    session: &str,
+    streams: u8,
+) -> Result<Vec<TcpStream>, Error> {
+    let deadline = Instant::now() + JOIN_TIMEOUT;
+    let timed_out = |_| Error::new(ErrorKind::TimedOut, "the sender's extra connections never showed up");
+    let mut joined: Vec<Option<TcpStream>> = (0..streams).map(|_| None).collect();
+    joined[0] = Some(primary);
+
+    while joined.iter().any(Option::is_none) {
+        let (mut stream, _) = timeout_at(deadline, listener.accept()).await.map_err(timed_out)??;
+
+        match timeout_at(deadline, read_join(&mut stream)).await {
+            Ok(Ok((their_session, index))) if their_session == session => {
+                if let Some(slot @ None) = joined.get_mut(index as usize) {
+                    *slot = Some(stream);
+                }
+            }
+            _ => continue,
+        }
+    }
+
+    Ok(joined.into_iter().flatten().collect())
+}
+
+// the sender's end of one range connection
+struct Outgoing<'a> {
+    writer: OwnedWriteHalf,
+    from_peer: UnboundedReceiver<Result<Frame, Error>>,
+    here: watch::Receiver<bool>,
+    control: &'a TransferControl,
+    tuning: Tuning,
+    // whether the receiver was told we paused, so it gets a Resume later
+    announced_pause: bool,
+}
+
+impl Outgoing<'_> {
+    // applies Pause, Resume and Cancel from the receiver, anything else is handed back
+    fn handle(&self, frame: Option<Result<Frame, Error>>) -> Result<Option<Frame>, Error> {
+        let frame = frame.ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "the receiver hung up"))??;
+
+        match frame.kind {
+            FrameKind::Pause => self.control.set_paused_by_peer(true),
+            FrameKind::Resume => self.control.set_paused_by_peer(false),
+            FrameKind::Cancel => return Err(cancelled_by_peer(frame.reason())),
+            FrameKind::KeepAlive => {}
+            _ => return Ok(Some(frame)),
+        }
+
+        Ok(None)
+    }
+
+    // called before every data frame: catches up on what the receiver said and sits out any pause
+    async fn ready(&mut self) -> Result<(), Error> {
+        let mut keepalive = None;
+
+        loop {
+            while let Ok(frame) = self.from_peer.try_recv() {
+                if let Some(frame) = self.handle(Some(frame))? {
+                    return Err(unexpected(frame.kind));
+                }
+            }
+
+            if self.control.is_cancelled() {
+                return Err(cancelled_here());
+            }
+
+            let paused_here = *self.here.borrow_and_update();
+
+            if paused_here != self.announced_pause {
+                let kind = if paused_here { FrameKind::Pause } else { FrameKind::Resume };
+                self.tuning.or_stall(Frame::control(kind).write_to(&mut self.writer)).await?;
+                self.announced_pause = paused_here;
+            }
+
+            if !self.control.is_paused() {
+                return Ok(());
+            }
+
+            let keepalive = keepalive.get_or_insert_with(|| keepalive_timer(self.tuning));
+            let Outgoing { writer, from_peer, here, control, tuning, .. } = self;
+
+            let frame = select! {
+                _ = control.cancelled() => continue,
+                _ = here.changed() => continue,
+                _ = keepalive.tick() => {
+                    tuning.or_stall(Frame::control(FrameKind::KeepAlive).write_to(writer)).await?;
+                    continue;
+                }
+                frame = from_peer.recv() => frame,
+            };
+
+            if let Some(frame) = self.handle(frame)? {
+                return Err(unexpected(frame.kind));
+            }
+        }
+    }
+
+    // End with the byte count, then wait for the receiver to Ack exactly that
+    async fn finish(&mut self, sent: u64) -> Result<(), Error> {
+        self.tuning.or_stall(Frame::end(sent).write_to(&mut self.writer)).await?;
+
+        loop {
+            let Outgoing { from_peer, control, tuning, .. } = self;
+
+            let frame = select! {
+                _ = control.cancelled() => return Err(cancelled_here()),
+                frame = control.or_stall(tuning.stall_timeout, async { Ok(from_peer.recv().await) }) => frame?,
+            };
+
+            match self.handle(frame)? {
+                Some(ack) if ack.kind == FrameKind::Ack && ack.count()? == sent => return Ok(()),
+                Some(ack) if ack.kind == FrameKind::Ack => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("the receiver only got {} of {} bytes", ack.count()?, sent),
+                    ))
+                }
+                Some(other) => return Err(unexpected(other.kind)),
+                None => continue,
+            }
+        }
+    }
+}
+
+// Sends one byte range of the file as frames and waits for the receiver to confirm it got exactly that much.
+// Hands the connection back for whatever comes after, with how many bytes the data took on the wire.
+pub async fn send_range(
+    stream: TcpStream,
+    path: &Path,
+    range: Range<u64>,
+    kind: FrameKind,
+    tuning: Tuning,
+    control: &TransferControl,
+    progress: &AtomicU64,
+) -> Result<(TcpStream, u64), Error> {
+    let (reader, writer) = stream.into_split();
+    let (frames, from_peer) = mpsc::unbounded_channel();
+    let forwarder = tokio::spawn(forward_frames(reader, frames));
+    let sent = range.end - range.start;
+
+    let mut out = Outgoing {
+        writer,
+        from_peer,
+        here: control.watch_here(),
+        control,
+        tuning,
+        announced_pause: false,
+    };
+
+    let result = match send_frames(&mut out, path, range, kind, progress).await {
+        Ok(wire_bytes) => out.finish(sent).await.map(|_| wire_bytes),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(wire_bytes) => {
+            // the forwarder stops by itself right after the Ack
+            let reader = forwarder.await.map_err(Error::other)?;
+            Ok((reader.reunite(out.writer).map_err(Error::other)?, wire_bytes))
+        }
+        Err(e) => {
+            forwarder.abort();
+
+            if e.kind() == ErrorKind::Interrupted {
+                let cancel = Frame::cancel("the sender cancelled the transfer");
+                let _ = tokio::time::timeout(CANCEL_GRACE, cancel.write_to(&mut out.writer)).await;
+            }
+
+            Err(e)
+        }
+    }
+}
+
+// everything the receiver says during a range ends up in `frames`, the Ack being the last of it
+async fn forward_frames(mut reader: OwnedReadHalf, frames: UnboundedSender<Result<Frame, Error>>) -> OwnedReadHalf {
+    loop {
+        let frame = Frame::read_from(&mut reader).await;
+        let last = !matches!(&frame, Ok(frame) if frame.kind != FrameKind::Ack);
+
+        if frames.send(frame).is_err() || last {
+            return reader;
+        }
+    }
+}
+
+async fn send_frames(
+    out: &mut Outgoing<'_>,
+    path: &Path,
+    range: Range<u64>,
+    kind: FrameKind,
+    progress: &AtomicU64,
+) -> Result<u64, Error> {
+    let mut file = File::open(path).await?;
+
+    #[cfg(target_os = "linux")]
+    if out.tuning.zero_copy && kind == FrameKind::Raw {
+        return send_frames_zero_copy(out, &file, range, progress).await;
+    }
+
+    file.seek(SeekFrom::Start(range.start)).await?;
+
+    let mut remaining = range.end - range.start;
+    let mut wire_bytes = 0;
+    let mut buffer = vec![0u8; out.tuning.chunk_size()];
+
+    while remaining > 0 {
+        out.ready().await?;
+
+        let to_read = remaining.min(buffer.len() as u64) as usize;
+        let read = file.read(&mut buffer[..to_read]).await?;
+
+        if read == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "the file got shorter while sending it"));
+        }
+
+        let frame = Frame::encode(kind, &buffer[..read]);
+        out.tuning.or_stall(frame.write_to(&mut out.writer)).await?;
+
+        wire_bytes += frame.wire_len();
+        remaining -= read as u64;
+        progress.fetch_add(read as u64, Ordering::Relaxed);
+    }
+
+    out.writer.flush().await?;
+    Ok(wire_bytes)
+}
+
+#[cfg(target_os = "linux")]
+async fn send_frames_zero_copy(
+    out: &mut Outgoing<'_>,
+    file: &File,
+    range: Range<u64>,
+    progress: &AtomicU64,
+) -> Result<u64, Error> {
+    let mut offset = range.start;
+    let mut wire_bytes = 0;
+
+    while offset < range.end {
+        out.ready().await?;
+
+        let len = (range.end - offset).min(out.tuning.chunk_size() as u64) as usize;
+        let writer = &mut out.writer;
+
+        out.tuning.or_stall(async {
+            Frame::write_header(writer, FrameKind::Raw, len as u32).await?;
+            zero_copy::send_file_range(writer.as_ref(), file, offset, len).await
+        })
+        .await?;
+
+        offset += len as u64;
+        wire_bytes += FRAME_HEADER_LEN + len as u64;
+        progress.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    Ok(wire_bytes)
+}
+
+// Writes one byte range into the (already preallocated) file at its offset. The range has to end with an End
+// frame carrying the exact byte count, anything past the range or an early End fails it.
+pub async fn receive_range(
+    stream: TcpStream,
+    path: &Path,
+    range: Range<u64>,
+    tuning: Tuning,
+    control: &TransferControl,
+    progress: &AtomicU64,
+) -> Result<(TcpStream, u64), Error> {
+    let (mut reader, writer) = stream.into_split();
+    let writer = Arc::new(Mutex::new(writer));
+    let announcer = tokio::spawn(announce_pauses(writer.clone(), control.clone(), tuning));
+
+    let result = select! {
+        // the connection is given up on either way, so it doesn't matter where this interrupts the read
+        _ = control.cancelled() => Err(cancelled_here()),
+        received = receive_frames(&mut reader, path, range.clone(), tuning, control, progress) => received,
+    };
+
+    announcer.abort();
+    let _ = announcer.await;
+    let mut writer = Arc::try_unwrap(writer).map_err(|_| Error::other("the writer is still in use"))?.into_inner();
+
+    match result {
+        Ok(wire_bytes) => {
+            tuning.or_stall(Frame::ack(range.end - range.start).write_to(&mut writer)).await?;
+            Ok((reader.reunite(writer).map_err(Error::other)?, wire_bytes))
+        }
+        Err(e) => {
+            if e.kind() == ErrorKind::Interrupted {
+                let cancel = Frame::cancel("the receiver cancelled the transfer");
+                let _ = tokio::time::timeout(CANCEL_GRACE, cancel.write_to(&mut writer)).await;
+            }
+
+            Err(e)
+        }
+    }
+}
+
+// passes our own pauses and resumes on to the sender while the range is coming in,
+// and keeps the connection from looking dead while either side has it paused
+async fn announce_pauses(writer: Arc<Mutex<OwnedWriteHalf>>, control: TransferControl, tuning: Tuning) {
+    let mut here = control.watch_here();
+    let mut announced = false;
+    let mut keepalive = keepalive_timer(tuning);
+
+    loop {
+        // checked before waiting, the pause might have happened before this range even started
+        let paused = *here.borrow_and_update();
+
+        if paused != announced {
+            let kind = if paused { FrameKind::Pause } else { FrameKind::Resume };
+            let mut writer = writer.lock().await;
+
+            if tuning.or_stall(Frame::control(kind).write_to(&mut *writer)).await.is_err() {
+                return;
+            }
+
+            announced = paused;
+        }
+
+        select! {
+            changed = here.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+
+            _ = keepalive.tick() => {
+                let mut writer = writer.lock().await;
+
+                if control.is_paused() && tuning.or_stall(Frame::control(FrameKind::KeepAlive).write_to(&mut *writer)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn keepalive_timer(tuning: Tuning) -> Interval {
+    let mut timer = tokio::time::interval_at(Instant::now() + tuning.keepalive_interval, tuning.keepalive_interval);
+    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    timer
+}
+
+async fn receive_frames(
+    reader: &mut OwnedReadHalf,
+    path: &Path,
+    range: Range<u64>,
+    tuning: Tuning,
+    control: &TransferControl,
+    progress: &AtomicU64,
+) -> Result<u64, Error> {
+    let mut file = OpenOptions::new().write(true).open(path).await?;
+    let mut offset = range.start;
+    let mut wire_bytes = 0;
+    // splice writes at an explicit offset and leaves the file position where it was
+    let mut seek_needed = true;
+
+    #[cfg(target_os = "linux")]
+    let pipe = match tuning.zero_copy {
+        true => Some(zero_copy::Pipe::new()?),
+        false => None,
+    };
+
+    loop {
+        let remaining = range.end - offset;
+        let cut_short = |e: Error| if e.kind() == ErrorKind::UnexpectedEof { short_range(remaining) } else { e };
+        let (kind, len) = control.or_stall(tuning.stall_timeout, Frame::read_header(reader)).await.map_err(cut_short)?;
+        wire_bytes += FRAME_HEADER_LEN + len as u64;
+
+        if kind != FrameKind::Raw && kind != FrameKind::Lz4 {
+            let frame = control.or_stall(tuning.stall_timeout, Frame::read_body(reader, kind, len)).await.map_err(cut_short)?;
+
+            match kind {
+                FrameKind::Pause => control.set_paused_by_peer(true),
+                FrameKind::Resume => control.set_paused_by_peer(false),
+                FrameKind::Cancel => return Err(cancelled_by_peer(frame.reason())),
+                FrameKind::KeepAlive => {}
+                FrameKind::End if remaining > 0 => return Err(short_range(remaining)),
+                FrameKind::End if frame.count()? == range.end - range.start => break,
+                FrameKind::End => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("the sender claims {} bytes but {} arrived", frame.count()?, range.end - range.start),
+                    ));
+                }
+                other => return Err(unexpected(other)),
+            }
+
+            continue;
+        }
+
+        #[cfg(target_os = "linux")]
+        if let (Some(pipe), FrameKind::Raw) = (&pipe, kind) {
+            if len as u64 > remaining {
+                return Err(over_long_range());
+            }
+
+            // whatever tokio still has in flight for this file has to land before we write around it
+            file.flush().await?;
+            control.or_stall(tuning.stall_timeout, pipe.splice_to_file(reader.as_ref(), &file, offset, len as usize)).await?;
+
+            offset += len as u64;
+            progress.fetch_add(len as u64, Ordering::Relaxed);
+            seek_needed = true;
+            continue;
+        }
+
+        let frame = control.or_stall(tuning.stall_timeout, Frame::read_body(reader, kind, len)).await.map_err(cut_short)?;
+
+        // checked before decoding, so a lying lz4 header can't make us allocate gigabytes, even on a huge range
+        let decoded_len = frame.decoded_len()?;
+
+        if decoded_len > MAX_CHUNK_SIZE as u64 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("a frame claims to decode to {} bytes, more than any chunk", decoded_len)));
+        }
+
+        if decoded_len > remaining {
+            return Err(over_long_range());
+        }
+
+        let chunk = frame.decode()?;
+
+        if chunk.len() as u64 > remaining {
+            return Err(over_long_range());
+        }
+
+        if seek_needed {
+            file.seek(SeekFrom::Start(offset)).await?;
+            seek_needed = false;
+        }
+
+        file.write_all(&chunk).await?;
+        offset += chunk.len() as u64;
+        progress.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    }
+
+    file.flush().await?;
+    Ok(wire_bytes)
+}
+
+fn short_range(missing: u64) -> Error {
+    Error::new(ErrorKind::UnexpectedEof, format!("the sender stopped {} bytes short", missing))
+}
+
+fn over_long_range() -> Error {
+    Error::new(ErrorKind::InvalidData, "the sender sent more data than it announced")
+}
+
+fn unexpected(kind: FrameKind) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("unexpected {:?} frame", kind))
+}
+
+// Every connection carries its own range at the same time. Hands the primary connection back
+// together with the total wire bytes.
+pub async fn send_all(
+    connections: Vec<TcpStream>,
+    path: PathBuf,
+    size: u64,
+    kind: FrameKind,
+    tuning: Tuning,
+    control: TransferControl,
+    progress: Arc<AtomicU64>,
+) -> Result<(TcpStream, u64), Error> {
+    let ranges = split_ranges(size, connections.len() as u8);
+    let mut tasks = JoinSet::new();
+
+    for (index, (stream, range)) in connections.into_iter().zip(ranges).enumerate() {
+        let path = path.clone();
+        let control = control.clone();
+        let progress = progress.clone();
+
+        tasks.spawn(async move {
+            let (stream, wire_bytes) = send_range(stream, &path, range, kind, tuning, &control, &progress).await?;
+            Ok((index, stream, wire_bytes))
+        });
+    }
+
+    wait_for_ranges(tasks, &control).await
+}
+
+pub async fn receive_all(
+    connections: Vec<TcpStream>,
+    path: PathBuf,
+    size: u64,
+    tuning: Tuning,
+    control: TransferControl,
+    progress: Arc<AtomicU64>,
+) -> Result<(TcpStream, u64), Error> {
+    let ranges = split_ranges(size, connections.len() as u8);
+    let mut tasks = JoinSet::new();
+
+    for (index, (stream, range)) in connections.into_iter().zip(ranges).enumerate() {
+        let path = path.clone();
+        let control = control.clone();
+        let progress = progress.clone();
+
+        tasks.spawn(async move {
+            let (stream, wire_bytes) = receive_range(stream, &path, range, tuning, &control, &progress).await?;
+            Ok((index, stream, wire_bytes))
+        });
+    }
+
+    wait_for_ranges(tasks, &control).await
+}
+
+// Any range failing fails the whole transfer. The other ranges get cancelled so they can tell the other side,
+// and the error that started it is the one that gets reported.
+async fn wait_for_ranges(mut tasks: RangeTasks, control: &TransferControl) -> Result<(TcpStream, u64), Error> {
+    let mut completed = vec![false; tasks.len()];
+    let mut primary = None;
+    let mut wire_bytes = 0;
+    let mut failure: Option<Error> = None;
+
+    while let Some(joined) = tasks.join_next().await {
+        match joined.map_err(Error::other).and_then(|range| range) {
+            Ok((index, stream, range_wire_bytes)) => {
+                completed[index] = true;
+                wire_bytes += range_wire_bytes;
+
+                if index == 0 {
+                    primary = Some(stream);
+                }
+            }
+            Err(e) => {
+                control.cancel();
+
+                if failure.as_ref().is_none_or(|failure| failure.kind() == ErrorKind::Interrupted) {
+                    failure = Some(e);
+                }
+            }
+        }
+    }
+
+    if let Some(failure) = failure {
+        return Err(failure);
+    }
+
+    match primary {
+        Some(primary) if completed.iter().all(|done| *done) => Ok((primary, wire_bytes)),
+        _ => Err(Error::new(ErrorKind::UnexpectedEof, "not every range made it")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::CHUNK_SIZE;
+    use tokio_util::sync::CancellationToken;
+
+    fn control() -> TransferControl {
+        TransferControl::new(CancellationToken::new())
+    }
+
+    async fn connected_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let connecting = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+        (connecting, accepted)
+    }
+
+    #[test]
+    fn test_split_ranges_cover_the_file() {
+        let ranges = split_ranges(10, 3);
+        assert_eq!(ranges, vec![0..3, 3..6, 6..10]);
+        assert_eq!(split_ranges(0, 4).iter().map(|r| r.end - r.start).sum::<u64>(), 0);
+        assert_eq!(split_ranges(5, 0), vec![0..5]);
+    }
+
+    // sends `source` into a preallocated `target` over `streams` loopback connections, returns how long that took
+    async fn transfer_on_loopback(source: &Path, target: &Path, streams: u8, kind: FrameKind, tuning: Tuning) -> Duration {
+        let size = std::fs::metadata(source).unwrap().len();
+        std::fs::File::create(target).unwrap().set_len(size).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let session = new_session();
+
+        let receiver_session = session.clone();
+        let receiver_target = target.to_path_buf();
+        let receiver = tokio::spawn(async move {
+            let (primary, _) = listener.accept().await.unwrap();
+            let connections = accept_joins(&listener, primary, &receiver_session, streams).await.unwrap();
+            receive_all(connections, receiver_target, size, tuning, control(), Arc::new(AtomicU64::new(0))).await.unwrap()
+        });
+
+        let started = std::time::Instant::now();
+        let mut connections = vec![TcpStream::connect(addr).await.unwrap()];
+
+        if streams > 1 {
+            // a stray connection with the wrong session must not get a range
+            let mut stray = TcpStream::connect(addr).await.unwrap();
+            write_join(&mut stray, "not-the-session", 1).await.unwrap();
+        }
+
+        // join in reverse to make sure the ranges are matched by index and not by arrival
+        let mut extra = Vec::new();
+        for index in (1..streams).rev() {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            write_join(&mut stream, &session, index).await.unwrap();
+            extra.push(stream);
+        }
+        extra.reverse();
+        connections.extend(extra);
+
+        let progress = Arc::new(AtomicU64::new(0));
+        send_all(connections, source.to_path_buf(), size, kind, tuning, control(), progress.clone()).await.unwrap();
+        receiver.await.unwrap();
+
+        assert_eq!(progress.load(Ordering::Relaxed), size);
+        started.elapsed()
+    }
+
+    #[tokio::test]
+    async fn test_parallel_streams_reassemble_on_loopback() {
+        let dir = std::env::temp_dir().join(format!("eel_parallel_{}", new_session()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("source.bin");
+        let target = dir.join("target.bin");
+
+        // a bit over a few chunks and not divisible by the stream count
+        let content: Vec<u8> = (0..CHUNK_SIZE * 5 + 1234).map(|_| rand::random::<u8>()).collect();
+        std::fs::write(&source, &content).unwrap();
+
+        let portable = Tuning {
+            chunk_size: CHUNK_SIZE,
+            zero_copy: false,
+            ..Default::default()
+        };
+
+        for (kind, tuning) in [(FrameKind::Raw, Tuning::default()), (FrameKind::Raw, portable), (FrameKind::Lz4, Tuning::default())] {
+            transfer_on_loopback(&source, &target, 4, kind, tuning).await;
+            assert_eq!(std::fs::read(&target).unwrap(), content, "{:?} {:?}", kind, tuning);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_silent_peers_stall_instead_of_hanging() {
+        let dir = std::env::temp_dir().join(format!("eel_stall_{}", new_session()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.bin");
+        std::fs::write(&path, vec![7u8; 64 * 1024 * 1024]).unwrap();
+
+        let tuning = Tuning {
+            stall_timeout: Duration::from_millis(300),
+            ..Default::default()
+        };
+
+        // receiver side: one frame arrives, then the sender just sits there
+        let (mut quiet_sender, receiving) = connected_pair().await;
+        Frame::encode(FrameKind::Raw, &[1, 2, 3]).write_to(&mut quiet_sender).await.unwrap();
+
+        let stalled = receive_range(receiving, &path, 0..1000, tuning, &control(), &AtomicU64::new(0)).await;
+        assert_eq!(stalled.unwrap_err().kind(), ErrorKind::TimedOut);
+
+        // sender side: the receiver never reads, so the socket buffers fill up and the writes stop going anywhere
+        let (sending, _quiet_receiver) = connected_pair().await;
+
+        let stalled = send_range(sending, &path, 0..64 * 1024 * 1024, FrameKind::Raw, tuning, &control(), &AtomicU64::new(0)).await;
+        assert_eq!(stalled.unwrap_err().kind(), ErrorKind::TimedOut);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // feeds `frames` to receive_range for a range of `len` bytes and returns what it made of them
+    async fn receive_from_fake_sender(len: u64, frames: Vec<Frame>, hang_up: bool) -> Result<u64, Error> {
+        let path = std::env::temp_dir().join(format!("eel_strict_{}", new_session()));
+        std::fs::File::create(&path).unwrap().set_len(len).unwrap();
+
+        let (mut sender, receiving) = connected_pair().await;
+
+        for frame in frames {
+            frame.write_to(&mut sender).await.unwrap();
+        }
+
+        if hang_up {
+            drop(sender);
+        } else {
+            // keep it open so the result can't come from an EOF
+            tokio::spawn(async move { tokio::time::sleep(Duration::from_secs(5)).await; drop(sender) });
+        }
+
+        let tuning = Tuning {
+            zero_copy: false,
+            stall_timeout: Duration::from_secs(2),
+            ..Default::default()
+        };
+        let result = receive_range(receiving, &path, 0..len, tuning, &control(), &AtomicU64::new(0)).await;
+        std::fs::remove_file(path).unwrap();
+        result.map(|(_, wire_bytes)| wire_bytes)
+    }
+
+    #[tokio::test]
+    async fn test_truncated_and_over_long_ranges_fail() {
+        let data = |len: usize| Frame::encode(FrameKind::Raw, &vec![1u8; len]);
+
+        let exact = receive_from_fake_sender(1000, vec![data(600), data(400), Frame::end(1000)], false).await;
+        assert!(exact.is_ok());
+
+        let hung_up = receive_from_fake_sender(1000, vec![data(600)], true).await.unwrap_err();
+        assert_eq!(hung_up.kind(), ErrorKind::UnexpectedEof);
+        assert!(hung_up.to_string().contains("400 bytes short"));
+
+        let ended_early = receive_from_fake_sender(1000, vec![data(600), Frame::end(600)], false).await.unwrap_err();
+        assert_eq!(ended_early.kind(), ErrorKind::UnexpectedEof);
+
+        let extra_frame = receive_from_fake_sender(1000, vec![data(1000), data(1), Frame::end(1001)], false).await.unwrap_err();
+        assert_eq!(extra_frame.kind(), ErrorKind::InvalidData);
+
+        let too_big = receive_from_fake_sender(1000, vec![data(600), data(600)], false).await.unwrap_err();
+        assert_eq!(too_big.kind(), ErrorKind::InvalidData);
+
+        let wrong_count = receive_from_fake_sender(1000, vec![data(1000), Frame::end(2000)], false).await.unwrap_err();
+        assert_eq!(wrong_count.kind(), ErrorKind::InvalidData);
+
+        // claims to decompress to 4GiB, must be refused without trying
+        let bomb = || {
+            let mut bomb = Frame::encode(FrameKind::Lz4, &[0u8; 10]);
+            bomb.payload[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+            bomb
+        };
+        let bombed = receive_from_fake_sender(1000, vec![bomb()], false).await.unwrap_err();
+        assert_eq!(bombed.kind(), ErrorKind::InvalidData);
+
+        // a range that's big enough to hold it still doesn't get a chunk that size (sparse, nothing is written)
+        let huge_range = receive_from_fake_sender(8 << 30, vec![bomb()], false).await.unwrap_err();
+        assert!(huge_range.to_string().contains("more than any chunk"));
+
+        let cancelled = receive_from_fake_sender(1000, vec![data(600), Frame::cancel("had enough")], false).await.unwrap_err();
+        assert_eq!(cancelled.kind(), ErrorKind::ConnectionAborted);
+        assert_eq!(cancelled.to_string(), "had enough");
+    }
+
+    async fn wait_until(condition: impl Fn() -> bool) {
+        while !condition() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pauses_and_cancels_reach_the_other_side() {
+        let dir = std::env::temp_dir().join(format!("eel_pause_{}", new_session()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("source.bin");
+        let target = dir.join("target.bin");
+
+        let size = 8 * 1024 * 1024;
+        let content: Vec<u8> = (0..size).map(|_| rand::random::<u8>()).collect();
+        std::fs::write(&source, &content).unwrap();
+        std::fs::File::create(&target).unwrap().set_len(size as u64).unwrap();
+
+        // short enough that sitting in a pause would trip it if pauses counted as stalls,
+        // and both ends get to deal with a few keepalives while waiting
+        let tuning = Tuning {
+            chunk_size: CHUNK_SIZE,
+            zero_copy: false,
+            stall_timeout: Duration::from_millis(300),
+            keepalive_interval: Duration::from_millis(50),
+        };
+
+        let transfer = |sending: TcpStream, receiving: TcpStream, sender: TransferControl, receiver: TransferControl| {
+            let (source, target) = (source.clone(), target.clone());
+            let sent = Arc::new(AtomicU64::new(0));
+            let progress = sent.clone();
+
+            let sending = tokio::spawn(async move {
+                send_range(sending, &source, 0..size as u64, FrameKind::Raw, tuning, &sender, &progress).await
+            });
+            let receiving = tokio::spawn(async move {
+                receive_range(receiving, &target, 0..size as u64, tuning, &receiver, &AtomicU64::new(0)).await
+            });
+
+            (sending, receiving, sent)
+        };
+
+        // the receiver pauses before anything moves, the sender has to hold off until it resumes
+        let (sender, receiver) = (control(), control());
+        receiver.pause();
+        let (sending, receiving) = connected_pair().await;
+        let (sending, receiving, sent) = transfer(sending, receiving, sender.clone(), receiver.clone());
+
+        wait_until(|| sender.is_paused_by_peer()).await;
+        let held_at = sent.load(Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(sent.load(Ordering::Relaxed), held_at);
+        assert!(held_at < size as u64);
+
+        receiver.resume();
+        sending.await.unwrap().unwrap();
+        receiving.await.unwrap().unwrap();
+        assert!(!sender.is_paused_by_peer());
+        assert_eq!(std::fs::read(&target).unwrap(), content);
+
+        // now the sender pauses and then gives up, the receiver has to hear about it instead of timing out
+        let (sender, receiver) = (control(), control());
+        sender.pause();
+        let (sending, receiving) = connected_pair().await;
+        let (sending, receiving, _) = transfer(sending, receiving, sender.clone(), receiver.clone());
+
+        wait_until(|| receiver.is_paused_by_peer()).await;
+        sender.cancel();
+        assert_eq!(sending.await.unwrap().unwrap_err().kind(), ErrorKind::Interrupted);
+
+        let cancelled = receiving.await.unwrap().unwrap_err();
+        assert_eq!(cancelled.kind(), ErrorKind::ConnectionAborted);
+        assert!(cancelled.to_string().contains("sender cancelled"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // cargo test --release bench_loopback_throughput -- --ignored --nocapture
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn bench_loopback_throughput() {
+        let dir = std::env::temp_dir().join(format!("eel_bench_{}", new_session()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("source.bin");
+        let target = dir.join("target.bin");
+
+        let size = 512 * 1024 * 1024;
+        let content: Vec<u8> = (0..size).map(|_| rand::random::<u8>()).collect();
+        std::fs::write(&source, &content).unwrap();
+        drop(content);
+
+        let runs = [
+            ("64KiB buffers, copying (the old path)", 1, Tuning { chunk_size: CHUNK_SIZE, zero_copy: false, ..Default::default() }),
+            ("1MiB buffers, copying", 1, Tuning { chunk_size: 1024 * 1024, zero_copy: false, ..Default::default() }),
+            ("1MiB frames, zero-copy", 1, Tuning::default()),
+            ("1MiB frames, zero-copy, 4 streams", 4, Tuning::default()),
+        ];
+
+        for (name, streams, tuning) in runs {
+            let took = transfer_on_loopback(&source, &target, streams, FrameKind::Raw, tuning).await;
+            let mib_per_sec = size as f64 / 1024.0 / 1024.0 / took.as_secs_f64();
+            println!("{:<40} {:>8.1} MiB/s", name, mib_per_sec);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
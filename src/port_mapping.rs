@@ -0,0 +1,227 @@
+use eel_file::EelError;
+use igd_next::aio::tokio::{search_gateway, Tokio};
+use igd_next::aio::Gateway;
+use igd_next::{PortMappingProtocol, SearchOptions};
+use std::net::{IpAddr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+// short lease so a crashed EelFile doesn't leave the port open forever, the listener renews it
+pub const LEASE_DURATION: Duration = Duration::from_secs(60 * 60);
+pub const RENEW_INTERVAL: Duration = Duration::from_secs(20 * 60);
+
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(5);
+const DESCRIPTION: &str = "EELFILE";
+
+pub struct PortMapping {
+    gateway: Gateway<Tokio>,
+    local_addr: SocketAddr,
+    pub external_addr: SocketAddrV4,
+}
+
+impl PortMapping {
+    pub async fn create(port: u16) -> Result<PortMapping, EelError> {
+        Self::create_with(port, SearchOptions {
+            timeout: Some(SEARCH_TIMEOUT),
+            ..Default::default()
+        })
+        .await
+    }
+
+    // asks the gateway to forward the same external port to us over UPnP IGD
+    pub async fn create_with(port: u16, search: SearchOptions) -> Result<PortMapping, EelError> {
+        let gateway = search_gateway(search)
+            .await
+            .map_err(|e| EelError::PortMappingError(format!("no UPnP gateway found ({})", e)))?;
+
+        let local_ip = Self::local_ip_towards(gateway.addr).await?;
+        let local_addr = SocketAddr::new(local_ip, port);
+
+        let IpAddr::V4(external_ip) = gateway
+            .get_external_ip()
+            .await
+            .map_err(|e| EelError::PortMappingError(e.to_string()))?
+        else {
+            return Err(EelError::PortMappingError("the gateway reported an IPv6 address.".to_string()));
+        };
+
+        let mapping = PortMapping {
+            gateway,
+            local_addr,
+            external_addr: SocketAddrV4::new(external_ip, port),
+        };
+
+        mapping.renew().await?;
+        Ok(mapping)
+    }
+
+    pub async fn renew(&self) -> Result<(), EelError> {
+        self.gateway
+            .add_port(
+                PortMappingProtocol::TCP,
+                self.external_addr.port(),
+                self.local_addr,
+                LEASE_DURATION.as_secs() as u32,
+                DESCRIPTION,
+            )
+            .await
+            .map_err(|e| EelError::PortMappingError(e.to_string()))
+    }
+
+    pub async fn remove(self) -> Result<(), EelError> {
+        self.gateway
+            .remove_port(PortMappingProtocol::TCP, self.external_addr.port())
+            .await
+            .map_err(|e| EelError::PortMappingError(e.to_string()))
+    }
+
+    // the gateway wants our LAN address, and the route towards it is the easiest way to find out which one that is
+    async fn local_ip_towards(gateway: SocketAddr) -> Result<IpAddr, EelError> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await.map_err(|e| EelError::Io(e.to_string()))?;
+        socket.connect(gateway).await.map_err(|e| EelError::Io(e.to_string()))?;
+
+        Ok(socket.local_addr().map_err(|e| EelError::Io(e.to_string()))?.ip())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    const ROOT_DESC: &str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+<device>
+<deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+<serviceList><service>
+<serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+<SCPDURL>/scpd.xml</SCPDURL>
+<controlURL>/ctl</controlURL>
+</service></serviceList>
+</device>
+</root>"#;
+
+    const SCPD: &str = r#"<?xml version="1.0"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0">
+<actionList>
+<action><name>GetExternalIPAddress</name><argumentList>
+<argument><name>NewExternalIPAddress</name><direction>out</direction></argument>
+</argumentList></action>
+<action><name>AddPortMapping</name><argumentList>
+<argument><name>NewRemoteHost</name><direction>in</direction></argument>
+<argument><name>NewExternalPort</name><direction>in</direction></argument>
+<argument><name>NewProtocol</name><direction>in</direction></argument>
+<argument><name>NewInternalPort</name><direction>in</direction></argument>
+<argument><name>NewInternalClient</name><direction>in</direction></argument>
+<argument><name>NewEnabled</name><direction>in</direction></argument>
+<argument><name>NewPortMappingDescription</name><direction>in</direction></argument>
+<argument><name>NewLeaseDuration</name><direction>in</direction></argument>
+</argumentList></action>
+<action><name>DeletePortMapping</name><argumentList>
+<argument><name>NewRemoteHost</name><direction>in</direction></argument>
+<argument><name>NewExternalPort</name><direction>in</direction></argument>
+<argument><name>NewProtocol</name><direction>in</direction></argument>
+</argumentList></action>
+</actionList>
+</scpd>"#;
+
+    fn soap_response(action: &str, body: &str) -> String {
+        format!(
+            r#"<?xml version="1.0"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body><u:{action}Response xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">{body}</u:{action}Response></s:Body></s:Envelope>"#
+        )
+    }
+
+    // just enough of an IGD to get through igd-next: SSDP reply, device description, SCPD and three SOAP actions
+    async fn spawn_fake_igd(actions: Arc<Mutex<Vec<String>>>) -> SocketAddr {
+        let http = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let http_addr = http.local_addr().unwrap();
+        let ssdp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let ssdp_addr = ssdp.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 1024];
+            while let Ok((_, from)) = ssdp.recv_from(&mut buffer).await {
+                let reply = format!("HTTP/1.1 200 OK\r\nLOCATION: http://{}/rootDesc.xml\r\n\r\n", http_addr);
+                let _ = ssdp.send_to(reply.as_bytes(), from).await;
+            }
+        });
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = http.accept().await {
+                let actions = actions.clone();
+
+                tokio::spawn(async move {
+                    let mut reader = BufReader::new(stream);
+                    let mut request_line = String::new();
+                    reader.read_line(&mut request_line).await.unwrap();
+
+                    let mut content_length = 0;
+                    let mut soap_action = String::new();
+                    loop {
+                        let mut header = String::new();
+                        reader.read_line(&mut header).await.unwrap();
+                        let header = header.trim_end();
+                        if header.is_empty() {
+                            break;
+                        }
+                        let (name, value) = header.split_once(':').unwrap();
+                        match name.to_ascii_lowercase().as_str() {
+                            "content-length" => content_length = value.trim().parse().unwrap(),
+                            "soapaction" => soap_action = value.trim().to_string(),
+                            _ => {}
+                        }
+                    }
+
+                    let mut body = vec![0u8; content_length];
+                    reader.read_exact(&mut body).await.unwrap();
+
+                    let response = if request_line.contains("/rootDesc.xml") {
+                        ROOT_DESC.to_string()
+                    } else if request_line.contains("/scpd.xml") {
+                        SCPD.to_string()
+                    } else {
+                        let action = soap_action.trim_matches('"').rsplit('#').next().unwrap().to_string();
+                        actions.lock().unwrap().push(action.clone());
+                        match action.as_str() {
+                            "GetExternalIPAddress" => soap_response(&action, "<NewExternalIPAddress>203.0.113.7</NewExternalIPAddress>"),
+                            _ => soap_response(&action, ""),
+                        }
+                    };
+
+                    let reply = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        response.len(),
+                        response
+                    );
+                    let _ = reader.get_mut().write_all(reply.as_bytes()).await;
+                });
+            }
+        });
+
+        ssdp_addr
+    }
+
+    #[tokio::test]
+    async fn test_map_and_unmap_against_fake_igd() {
+        let actions = Arc::new(Mutex::new(Vec::new()));
+        let ssdp_addr = spawn_fake_igd(actions.clone()).await;
+
+        let search = SearchOptions {
+            bind_addr: "127.0.0.1:0".parse().unwrap(),
+            broadcast_address: ssdp_addr,
+            timeout: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+
+        let mapping = PortMapping::create_with(4000, search).await.unwrap();
+        assert_eq!(mapping.external_addr, "203.0.113.7:4000".parse().unwrap());
+
+        mapping.remove().await.unwrap();
+        assert_eq!(
+            *actions.lock().unwrap(),
+            vec!["GetExternalIPAddress", "AddPortMapping", "DeletePortMapping"]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use crate::transfer_code::SessionKey;
+use eel_file::Compression;
+use sha2::{Digest, Sha256};
+use std::io::{Error, ErrorKind, Read};
+use std::path::PathBuf;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub const CHUNK_SIZE: usize = 64 * 1024;
+pub const MAX_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+pub const FRAME_HEADER_LEN: u64 = 5;
+
+// lz4 can grow incompressible input a little, anything past this is garbage or an attack
+const MAX_FRAME_LEN: u32 = (MAX_CHUNK_SIZE as u32) * 2;
+
+// only bother compressing if the first chunk shrinks to less than this fraction of its size
+const WORTH_COMPRESSING_RATIO: f32 = 0.9;
+
+// Every frame is [kind: u8][payload length: u32 BE][payload].
+// A range is its data frames, then End from the sender and Ack from the receiver, both carrying the byte count.
+// Pause, Resume and Cancel can go either way at any point in between, KeepAlive too while paused.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameKind {
+    Raw = 0,
+    Lz4 = 1,
+    End = 2,
+    Ack = 3,
+    Cancel = 4,
+    Pause = 5,
+    Resume = 6,
+    KeepAlive = 7,
+}
+
+impl FrameKind {
+    fn from_byte(byte: u8) -> Result<FrameKind, Error> {
+        match byte {
+            0 => Ok(FrameKind::Raw),
+            1 => Ok(FrameKind::Lz4),
+            2 => Ok(FrameKind::End),
+            3 => Ok(FrameKind::Ack),
+            4 => Ok(FrameKind::Cancel),
+            5 => Ok(FrameKind::Pause),
+            6 => Ok(FrameKind::Resume),
+            7 => Ok(FrameKind::KeepAlive),
+            other => Err(Error::new(ErrorKind::InvalidData, format!("unknown frame kind {}", other))),
+        }
+    }
+}
+
+pub struct Frame {
+    pub kind: FrameKind,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    // turns a chunk of the file into a frame of the given kind
+    pub fn encode(kind: FrameKind, chunk: &[u8]) -> Frame {
+        let payload = match kind {
+            FrameKind::Lz4 => lz4_flex::compress_prepend_size(chunk),
+            _ => chunk.to_vec(),
+        };
+
+        Frame { kind, payload }
+    }
+
+    // Pause and Resume, no payload
+    pub fn control(kind: FrameKind) -> Frame {
+        Frame { kind, payload: Vec::new() }
+    }
+
+    pub fn cancel(reason: &str) -> Frame {
+        Frame {
+            kind: FrameKind::Cancel,
+            payload: reason.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn reason(&self) -> String {
+        String::from_utf8_lossy(&self.payload).to_string()
+    }
+
+    pub fn end(sent: u64) -> Frame {
+        Frame {
+            kind: FrameKind::End,
+            payload: sent.to_be_bytes().to_vec(),
+        }
+    }
+
+    pub fn ack(received: u64) -> Frame {
+        Frame {
+            kind: FrameKind::Ack,
+            payload: received.to_be_bytes().to_vec(),
+        }
+    }
+
+    // the byte count carried by End and Ack
+    pub fn count(&self) -> Result<u64, Error> {
+        match (self.kind, <[u8; 8]>::try_from(self.payload.as_slice())) {
+            (FrameKind::End | FrameKind::Ack, Ok(count)) => Ok(u64::from_be_bytes(count)),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("expected a byte count, got a {:?} frame", self.kind))),
+        }
+    }
+
+    // how big the chunk will be once decoded, known before actually decompressing anything
+    pub fn decoded_len(&self) -> Result<u64, Error> {
+        match self.kind {
+            FrameKind::Raw => Ok(self.payload.len() as u64),
+            // lz4_flex prepends the original size as u32 LE
+            FrameKind::Lz4 => match <[u8; 4]>::try_from(self.payload.get(..4).unwrap_or_default()) {
+                Ok(size) => Ok(u32::from_le_bytes(size) as u64),
+                Err(_) => Err(Error::new(ErrorKind::InvalidData, "lz4 frame is too short")),
+            },
+            _ => Err(Error::new(ErrorKind::InvalidData, "not a data frame")),
+        }
+    }
+
+    // and back into the original chunk. No sender makes chunks bigger than MAX_CHUNK_SIZE, a frame that says it
+    // decompresses to more is lying and gets refused before lz4 allocates whatever it asked for.
+    pub fn decode(self) -> Result<Vec<u8>, Error> {
+        match self.kind {
+            FrameKind::Raw => Ok(self.payload),
+            FrameKind::Lz4 if self.decoded_len()? > MAX_CHUNK_SIZE as u64 => {
+                Err(Error::new(ErrorKind::InvalidData, "lz4 frame decompresses to more than a chunk"))
+            }
+            FrameKind::Lz4 => lz4_flex::decompress_size_prepended(&self.payload)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string())),
+            _ => Err(Error::new(ErrorKind::InvalidData, "not a data frame")),
+        }
+    }
+
+    // bytes this frame takes on the wire, header included
+    pub fn wire_len(&self) -> u64 {
+        FRAME_HEADER_LEN + self.payload.len() as u64
+    }
+
+    pub async fn write_to<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> Result<(), Error> {
+        Self::write_header(writer, self.kind, self.payload.len() as u32).await?;
+        writer.write_all(&self.payload).await
+    }
+
+    pub async fn read_from<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Frame, Error> {
+        let (kind, len) = Self::read_header(reader).await?;
+        Self::read_body(reader, kind, len).await
+    }
+
+    // the rest of a frame whose header was already read
+    pub async fn read_body<R: AsyncRead + Unpin>(reader: &mut R, kind: FrameKind, len: u32) -> Result<Frame, Error> {
+        let mut payload = vec![0u8; len as usize];
+        reader.read_exact(&mut payload).await?;
+
+        Ok(Frame { kind, payload })
+    }
+
+    // the header on its own is for the zero-copy path, where the payload never passes through our memory
+    pub async fn write_header<W: AsyncWrite + Unpin>(writer: &mut W, kind: FrameKind, len: u32) -> Result<(), Error> {
+        writer.write_all(&[&[kind as u8][..], &len.to_be_bytes()].concat()).await
+    }
+
+    pub async fn read_header<R: AsyncRead + Unpin>(reader: &mut R) -> Result<(FrameKind, u32), Error> {
+        let kind = FrameKind::from_byte(reader.read_u8().await?)?;
+        let len = reader.read_u32().await?;
+
+        if len > MAX_FRAME_LEN {
+            return Err(Error::new(ErrorKind::InvalidData, format!("frame of {} bytes is too large", len)));
+        }
+
+        Ok((kind, len))
+    }
+}
+
+// Picks the frame kind for the whole transfer from the first chunk. Already compressed files
+// (videos, archives) would only waste CPU, logs and CSV dumps usually shrink a lot.
+pub fn choose_frame_kind(accepted: &[Compression], sample: &[u8]) -> FrameKind {
+    if !accepted.contains(&Compression::Lz4) || sample.is_empty() {
+        return FrameKind::Raw;
+    }
+
+    let compressed = lz4_flex::compress_prepend_size(sample);
+
+    if (compressed.len() as f32) < sample.len() as f32 * WORTH_COMPRESSING_RATIO {
+        FrameKind::Lz4
+    } else {
+        FrameKind::Raw
+    }
+}
+
+// The receiver's answer to an offer: "HAND IT OVER lz4 streams=4 session=1a2b3c4d5e6f7a8b".
+// Unknown words are skipped, so older peers just see whatever part of it they understand.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Acceptance {
+    pub compression: Vec<Compression>,
+    pub streams: u8,
+    // only needed when streams > 1, the extra connections present it to join the transfer
+    pub session: Option<String>,
+}
+
+impl Acceptance {
+    pub fn to_line(&self) -> String {
+        let mut line = "HAND IT OVER".to_string();
+
+        for compression in &self.compression {
+            line += &format!(" {}", compression.name());
+        }
+
+        if let Some(session) = &self.session {
+            line += &format!(" streams={} session={}", self.streams, session);
+        }
+
+        line + "\r\n"
+    }
+
+    pub fn parse(line: &str) -> Option<Acceptance> {
+        let words = line.trim_end().strip_prefix("HAND IT OVER")?;
+        let mut acceptance = Acceptance {
+            compression: Vec::new(),
+            streams: 1,
+            session: None,
+        };
+
+        for word in words.split_whitespace() {
+            if let Some(streams) = word.strip_prefix("streams=") {
+                acceptance.streams = streams.parse().unwrap_or(1).max(1);
+            } else if let Some(session) = word.strip_prefix("session=") {
+                acceptance.session = Some(session.to_string());
+            } else if let Some(compression) = Compression::from_name(word) {
+                acceptance.compression.push(compression);
+            }
+        }
+
+        if acceptance.session.is_none() {
+            acceptance.streams = 1;
+        }
+
+        Some(acceptance)
+    }
+}
+
+// Once every range is in and synced to disk, the receiver says what it ended up with:
+// "STORED OK <sha256>" or "FAILED <reason>". The sender compares the hash and answers VERIFIED or MISMATCH,
+// and the receiver only keeps the file once VERIFIED arrives.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Stored(String),
+    Failed(String),
+}
+
+pub const VERIFIED: &str = "VERIFIED";
+pub const MISMATCH: &str = "MISMATCH";
+
+impl Outcome {
+    pub fn to_line(&self) -> String {
+        match self {
+            Outcome::Stored(digest) => format!("STORED OK {}\r\n", digest),
+            Outcome::Failed(reason) => format!("FAILED {}\r\n", reason.replace(['\r', '\n'], " ")),
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<Outcome> {
+        let line = line.trim_end();
+
+        if let Some(digest) = line.strip_prefix("STORED OK ") {
+            Some(Outcome::Stored(digest.to_string()))
+        } else {
+            line.strip_prefix("FAILED ").map(|reason| Outcome::Failed(reason.to_string()))
+        }
+    }
+}
+
+// With a transfer code the lines that decide what happens to the file get " SEAL <hmac>" tacked on. `context`
+// goes into the seal as well, so a line can't be lifted from one spot in the exchange and replayed in another.
+pub fn seal_line(line: &str, key: Option<&SessionKey>, context: &str) -> String {
+    match key {
+        Some(key) => format!("{} SEAL {}\r\n", line, key.seal(&format!("{} {}", context, line))),
+        None => format!("{}\r\n", line),
+    }
+}
+
+// the line without its seal, or None if the seal is missing or wrong. Without a key there's nothing to check.
+pub fn open_line<'a>(line: &'a str, key: Option<&SessionKey>, context: &str) -> Option<&'a str> {
+    let line = line.trim_end();
+
+    match key {
+        Some(key) => {
+            let (body, seal) = line.rsplit_once(" SEAL ")?;
+            key.opens(&format!("{} {}", context, body), seal).then_some(body)
+        }
+        None => Some(line),
+    }
+}
+
+// sha256 of the whole file as hex, read on a blocking thread since it can take a while for big files
+pub async fn file_digest(path: PathBuf) -> Result<String, Error> {
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 1024 * 1024];
+
+        loop {
+            match file.read(&mut buffer)? {
+                0 => break,
+                read => hasher.update(&buffer[..read]),
+            }
+        }
+
+        Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+    })
+    .await
+    .map_err(Error::other)?
+}
+
+pub fn compression_ratio(original: u64, on_wire: u64) -> f32 {
+    if on_wire == 0 {
+        1.0
+    } else {
+        original as f32 / on_wire as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frames_round_trip_and_pick_compression() {
+        let text = "timestamp,level,message\n".repeat(4000);
+        let noise: Vec<u8> = (0..CHUNK_SIZE).map(|_| rand::random::<u8>()).collect();
+
+        assert_eq!(choose_frame_kind(&[Compression::Lz4], text.as_bytes()), FrameKind::Lz4);
+        assert_eq!(choose_frame_kind(&[Compression::Lz4], &noise), FrameKind::Raw);
+        assert_eq!(choose_frame_kind(&[], text.as_bytes()), FrameKind::Raw);
+
+        let mut wire = Vec::new();
+        Frame::encode(FrameKind::Lz4, text.as_bytes()).write_to(&mut wire).await.unwrap();
+        Frame::encode(FrameKind::Raw, &noise).write_to(&mut wire).await.unwrap();
+
+        let mut reader = wire.as_slice();
+        for expected in [text.as_bytes(), &noise] {
+            let (kind, len) = Frame::read_header(&mut reader).await.unwrap();
+            let frame = Frame::read_body(&mut reader, kind, len).await.unwrap();
+            assert_eq!(frame.decode().unwrap(), expected);
+        }
+
+        let mut bomb = Frame::encode(FrameKind::Lz4, &[0u8; 10]);
+        bomb.payload[..4].copy_from_slice(&(MAX_CHUNK_SIZE as u32 + 1).to_le_bytes());
+        assert_eq!(bomb.decode().unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn test_outcome_lines_and_digest() {
+        let stored = Outcome::Stored("abc123".to_string());
+        assert_eq!(Outcome::parse(&stored.to_line()), Some(stored));
+
+        let failed = Outcome::Failed("disk full\r\noops".to_string());
+        assert_eq!(Outcome::parse(&failed.to_line()), Some(Outcome::Failed("disk full  oops".to_string())));
+        assert_eq!(Outcome::parse("HAND IT OVER\r\n"), None);
+
+        let path = std::env::temp_dir().join(format!("eel_digest_{}", rand::random::<u64>()));
+        std::fs::write(&path, b"abc").unwrap();
+        let digest = file_digest(path.clone()).await.unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(digest, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+}
@@ -0,0 +1,312 @@
+use crate::EelError;
+use std::collections::HashMap;
+use std::net::SocketAddrV4;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+
+pub const DEFAULT_RELAY_PORT: u16 = 47500;
+
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Nothing on the relay says a waiting peer is still there, so they're sent off after a while and have to dial back in.
+// That way dead connections don't pile up, and the cap keeps a flood of sessions from eating all the sockets.
+#[derive(Clone, Copy, Debug)]
+pub struct RelayLimits {
+    pub wait_timeout: Duration,
+    pub max_waiting: usize,
+}
+
+impl Default for RelayLimits {
+    fn default() -> Self {
+        RelayLimits {
+            wait_timeout: Duration::from_secs(10 * 60),
+            max_waiting: 1000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RelayConfig {
+    pub addr: SocketAddrV4,
+    pub session: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RelayRole {
+    Sender,
+    Listener,
+}
+
+impl RelayRole {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RelayRole::Sender => "send",
+            RelayRole::Listener => "listen",
+        }
+    }
+
+    fn parse(role: &str) -> Option<RelayRole> {
+        match role {
+            "send" => Some(RelayRole::Sender),
+            "listen" => Some(RelayRole::Listener),
+            _ => None,
+        }
+    }
+}
+
+struct Waiting {
+    role: RelayRole,
+    stream: TcpStream,
+    since: Instant,
+}
+
+type WaitingPeers = Arc<Mutex<HashMap<String, Waiting>>>;
+
+// Connects out to the relay and blocks until a peer with the same session and the opposite role shows up.
+// The returned stream then behaves like a direct connection to that peer.
+pub async fn connect_via_relay(relay: &RelayConfig, role: RelayRole) -> Result<TcpStream, EelError> {
+    'dial: loop {
+        let mut stream = TcpStream::connect(relay.addr)
+            .await
+            .map_err(|e| EelError::ConnectionError(e.to_string()))?;
+
+        let hello = format!("EEL RELAY {} {}\n", role.as_str(), relay.session);
+        stream.write_all(hello.as_bytes()).await.map_err(|e| EelError::RelayError(e.to_string()))?;
+
+        loop {
+            match read_line(&mut stream).await?.as_str() {
+                "WAITING" => continue,
+                "PAIRED" => return Ok(stream),
+                // waited too long for the relay's taste, get back in line
+                "EXPIRED" => continue 'dial,
+                other => return Err(EelError::RelayError(other.to_string())),
+            }
+        }
+    }
+}
+
+// The relay only ever reads the hello line. Everything after PAIRED is spliced through untouched,
+// so once transfers are encrypted end to end it never gets to see any plaintext.
+pub async fn run_relay(listener: TcpListener, limits: RelayLimits, token: CancellationToken) {
+    let waiting: WaitingPeers = Arc::new(Mutex::new(HashMap::new()));
+    let mut sweep = tokio::time::interval(limits.wait_timeout / 4);
+
+    loop {
+        select! {
+            _ = token.cancelled() => return,
+
+            _ = sweep.tick() => expire_waiting(&waiting, limits.wait_timeout),
+
+            accepted = listener.accept() => {
+                let Ok((stream, addr)) = accepted else { continue };
+                let waiting = waiting.clone();
+                let token = token.clone();
+                // the session isn't known until the hello's been read
+                let span = tracing::info_span!("client", peer = %addr, session = Empty, role = Empty);
+
+                tokio::spawn(async move {
+                    if let Err(e) = handle_relay_client(stream, waiting, limits, token).await {
+                        tracing::warn!("Dropped {}: {}", addr, e);
+                    }
+                }.instrument(span));
+            }
+        }
+    }
+}
+
+async fn handle_relay_client(
+    mut stream: TcpStream,
+    waiting: WaitingPeers,
+    limits: RelayLimits,
+    token: CancellationToken,
+) -> Result<(), EelError> {
+    let (role, session) = match tokio::time::timeout(HELLO_TIMEOUT, read_hello(&mut stream)).await {
+        Ok(hello) => hello?,
+        Err(_) => return Err(EelError::RelayError("no hello received in time.".to_string())),
+    };
+    Span::current().record("session", session.as_str()).record("role", role.as_str());
+
+    let paired = {
+        let mut waiting = waiting.lock().unwrap();
+
+        match waiting.remove(&session) {
+            Some(peer) if peer.role != role => Some(peer.stream),
+            None if waiting.len() >= limits.max_waiting => None,
+            // same role twice means the first one most likely reconnected, the newer connection wins
+            _ => {
+                waiting.insert(session.clone(), Waiting { role, stream, since: Instant::now() });
+                drop(waiting);
+                tracing::info!("Session {}: {} is waiting for a peer.", session, role.as_str());
+                return Ok(());
+            }
+        }
+    };
+
+    let Some(mut peer) = paired else {
+        let _ = stream.write_all(b"RELAY FULL\n").await;
+        return Err(EelError::RelayError(format!("{} peers are already waiting.", limits.max_waiting)));
+    };
+
+    let relay_error = |e: std::io::Error| EelError::RelayError(e.to_string());
+    stream.write_all(b"PAIRED\n").await.map_err(relay_error)?;
+    peer.write_all(b"PAIRED\n").await.map_err(relay_error)?;
+    tracing::info!("Session {}: peers paired, splicing.", session);
+
+    select! {
+        _ = token.cancelled() => {}
+        copied = tokio::io::copy_bidirectional(&mut stream, &mut peer) => {
+            match copied {
+                Ok((a, b)) => tracing::info!(bytes = a + b, "Session {}: finished after relaying {} bytes.", session, a + b),
+                Err(e) => tracing::warn!("Session {}: ended with error: {}", session, e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// the peers that waited too long are told so and hung up on, they dial back in if they're still around
+fn expire_waiting(waiting: &WaitingPeers, wait_timeout: Duration) {
+    let expired: Vec<(String, Waiting)> = {
+        let mut waiting = waiting.lock().unwrap();
+        let sessions: Vec<String> = waiting.iter().filter(|(_, peer)| peer.since.elapsed() >= wait_timeout).map(|(session, _)| session.clone()).collect();
+        sessions.into_iter().filter_map(|session| waiting.remove_entry(&session)).collect()
+    };
+
+    for (session, mut peer) in expired {
+        tracing::info!("Session {}: {} waited too long, sent it off.", session, peer.role.as_str());
+        tokio::spawn(async move {
+            let _ = peer.stream.write_all(b"EXPIRED\n").await;
+        });
+    }
+}
+
+// one byte at a time on purpose: whatever follows the newline belongs to the peer and must not get buffered away
+async fn read_line(stream: &mut TcpStream) -> Result<String, EelError> {
+    let mut line = Vec::new();
+
+    loop {
+        let byte = stream.read_u8().await.map_err(|e| EelError::RelayError(e.to_string()))?;
+
+        if byte == b'\n' {
+            return Ok(String::from_utf8_lossy(&line).trim_end().to_string());
+        }
+
+        line.push(byte);
+
+        if line.len() > 256 {
+            return Err(EelError::RelayError("line too long.".to_string()));
+        }
+    }
+}
+
+// expects "EEL RELAY <role> <session>"
+async fn read_hello(stream: &mut TcpStream) -> Result<(RelayRole, String), EelError> {
+    let line = read_line(stream).await?;
+    let mut parts = line.split(' ');
+
+    match (parts.next(), parts.next(), parts.next().and_then(RelayRole::parse), parts.next()) {
+        (Some("EEL"), Some("RELAY"), Some(role), Some(session)) if !session.is_empty() => {
+            stream.write_all(b"WAITING\n").await.map_err(|e| EelError::RelayError(e.to_string()))?;
+            Ok((role, session.to_string()))
+        }
+        _ => {
+            let _ = stream.write_all(b"BAD HELLO\n").await;
+            Err(EelError::RelayError(format!("malformed hello: {}", line)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, SocketAddr};
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    #[tokio::test]
+    async fn test_relay_splices_matching_sessions() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let SocketAddr::V4(addr) = listener.local_addr().unwrap() else { unreachable!() };
+        let token = CancellationToken::new();
+        tokio::spawn(run_relay(listener, RelayLimits::default(), token.clone()));
+
+        let config = RelayConfig {
+            addr: SocketAddrV4::new(Ipv4Addr::LOCALHOST, addr.port()),
+            session: "moray".to_string(),
+        };
+
+        let listen_config = config.clone();
+        let listen_side = tokio::spawn(async move { connect_via_relay(&listen_config, RelayRole::Listener).await });
+
+        // make sure the listener is the one waiting
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut sender = connect_via_relay(&config, RelayRole::Sender).await.unwrap();
+        let mut listener = listen_side.await.unwrap().unwrap();
+
+        sender.write_all(b"eels are fish").await.unwrap();
+        let mut received = [0u8; 13];
+        listener.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"eels are fish");
+
+        listener.write_all(b"ok").await.unwrap();
+        let mut reply = [0u8; 2];
+        sender.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"ok");
+
+        token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_relay_rejects_malformed_hello() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(run_relay(listener, RelayLimits::default(), CancellationToken::new()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GIVE ME FILES\n").await.unwrap();
+
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).await.unwrap();
+        assert_eq!(reply, "BAD HELLO\n");
+    }
+
+    #[tokio::test]
+    async fn test_waiting_peers_are_capped_and_expire() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let limits = RelayLimits { wait_timeout: Duration::from_millis(300), max_waiting: 1 };
+        tokio::spawn(run_relay(listener, limits, CancellationToken::new()));
+
+        let wait = |session: &'static str| async move {
+            let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+            stream.get_mut().write_all(format!("EEL RELAY listen {}\n", session).as_bytes()).await.unwrap();
+            let mut reply = String::new();
+            stream.read_line(&mut reply).await.unwrap();
+            assert_eq!(reply, "WAITING\n");
+            stream
+        };
+
+        let mut first = wait("moray").await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut second = wait("conger").await;
+        let mut reply = String::new();
+        second.read_line(&mut reply).await.unwrap();
+        assert_eq!(reply, "RELAY FULL\n");
+
+        reply.clear();
+        tokio::time::timeout(Duration::from_secs(2), first.read_line(&mut reply)).await.unwrap().unwrap();
+        assert_eq!(reply, "EXPIRED\n");
+
+        // and the slot is free again
+        let mut third = wait("conger").await;
+        reply.clear();
+        assert!(tokio::time::timeout(Duration::from_millis(100), third.read_line(&mut reply)).await.is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use crate::eel_log::LogLevel;
+use crate::{EelError, SizeUnits, Util};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// what to do when a file with the incoming name is already in the folder
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum ConflictPolicy {
+    // tell the sender no, nothing on disk gets touched
+    #[default]
+    Refuse,
+    // save it next to the old one as "name (1).ext"
+    Rename,
+    Overwrite,
+}
+
+impl std::fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConflictPolicy::Refuse => write!(f, "Refuse the file"),
+            ConflictPolicy::Rename => write!(f, "Keep both"),
+            ConflictPolicy::Overwrite => write!(f, "Overwrite"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum Theme {
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+// which desktop notifications show up while the window is in the background
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct NotificationSettings {
+    pub offer: bool,
+    pub complete: bool,
+    pub failed: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings {
+            offer: false,
+            complete: true,
+            failed: true,
+        }
+    }
+}
+
+// the odds of each kind of eel fact on startup, in percent
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct FactOdds {
+    pub normal: u8,
+    pub sus: u8,
+    pub amogus: u8,
+    pub insanity: u8,
+}
+
+impl Default for FactOdds {
+    fn default() -> Self {
+        FactOdds {
+            normal: 50,
+            sus: 40,
+            amogus: 9,
+            insanity: 1,
+        }
+    }
+}
+
+// Everything that can be changed from the settings window. Missing fields fall back to their defaults so
+// older settings files keep loading.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub chunk_size_kib: u32,
+    pub stall_timeout_secs: u64,
+    pub keepalive_secs: u64,
+    pub conflict: ConflictPolicy,
+    pub theme: Theme,
+    pub size_units: SizeUnits,
+    pub log_level: LogLevel,
+    pub auto_listen: bool,
+    // closing the window leaves the app running in the tray, for machines that just sit there receiving
+    pub run_in_tray: bool,
+    pub notifications: NotificationSettings,
+    // the last port and folder the listener was started with, auto-listen goes back to those
+    pub listen_port: Option<u16>,
+    pub listen_dir: Option<PathBuf>,
+    pub fact_odds: FactOdds,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            chunk_size_kib: 1024,
+            stall_timeout_secs: 30,
+            keepalive_secs: 15,
+            conflict: ConflictPolicy::default(),
+            theme: Theme::default(),
+            size_units: SizeUnits::default(),
+            log_level: LogLevel::default(),
+            auto_listen: false,
+            run_in_tray: false,
+            notifications: NotificationSettings::default(),
+            listen_port: None,
+            listen_dir: None,
+            fact_odds: FactOdds::default(),
+        }
+    }
+}
+
+// the frames can't get bigger than what the receiver is willing to buffer
+pub const MAX_CHUNK_SIZE_KIB: u32 = 4 * 1024;
+
+impl Settings {
+    pub fn default_path() -> PathBuf {
+        Util::app_data_dir().join("settings.json")
+    }
+
+    // a missing file means nothing has been changed yet
+    pub fn load(path: &Path) -> Result<Settings, EelError> {
+        let settings: Settings = Util::load_json(path, EelError::ConfigError)?;
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), EelError> {
+        Util::write_json(path, self, EelError::ConfigError)
+    }
+
+    pub fn validate(&self) -> Result<(), EelError> {
+        if !(4..=MAX_CHUNK_SIZE_KIB).contains(&self.chunk_size_kib) {
+            return Err(EelError::ConfigError(format!("the buffer size has to be between 4 and {} KiB", MAX_CHUNK_SIZE_KIB)));
+        }
+
+        if self.stall_timeout_secs < 5 {
+            return Err(EelError::ConfigError("the stall timeout has to be at least 5 seconds".to_string()));
+        }
+
+        // otherwise a paused transfer times out between two keepalives
+        if self.keepalive_secs == 0 || self.keepalive_secs >= self.stall_timeout_secs {
+            return Err(EelError::ConfigError("keepalives have to be sent more often than the stall timeout".to_string()));
+        }
+
+        let odds = self.fact_odds;
+        if odds.normal as u32 + odds.sus as u32 + odds.amogus as u32 + odds.insanity as u32 != 100 {
+            return Err(EelError::ConfigError("the eel fact odds have to add up to 100%".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation_and_old_files() {
+        assert!(Settings::default().validate().is_ok());
+
+        let keepalive_too_slow = Settings { keepalive_secs: 30, ..Settings::default() };
+        assert!(keepalive_too_slow.validate().is_err());
+
+        let huge_buffers = Settings { chunk_size_kib: MAX_CHUNK_SIZE_KIB + 1, ..Settings::default() };
+        assert!(huge_buffers.validate().is_err());
+
+        let mut odds = Settings::default();
+        odds.fact_odds.insanity = 2;
+        assert!(odds.validate().is_err());
+
+        // whatever a file doesn't mention keeps its default
+        let old: Settings = serde_json::from_str(r#"{ "theme": "Dark", "auto_listen": true }"#).unwrap();
+        assert_eq!(old.theme, Theme::Dark);
+        assert!(old.auto_listen);
+        assert_eq!(old.chunk_size_kib, Settings::default().chunk_size_kib);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{Animation, AppState, EelError};
+
+// how the last run ended, it decides which idle animation plays
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ending {
+    Quietly,
+    Success,
+    Failure,
+}
+
+// The states one side of the app goes through, a listener or a single send. Changes that make no sense
+// are refused and leave the state as it was, and the animation always follows from where we are.
+pub struct StateMachine {
+    state: AppState,
+    ending: Ending,
+    // a pause can only be lifted back into the transfer it interrupted
+    paused_from: Option<AppState>,
+    // set once Listening, only a listener accepts files and has something to go back to afterwards
+    listener: bool,
+}
+
+impl Default for StateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateMachine {
+    pub fn new() -> StateMachine {
+        StateMachine {
+            state: AppState::Idle,
+            ending: Ending::Quietly,
+            paused_from: None,
+            listener: false,
+        }
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    pub fn is_legal(&self, to: &AppState) -> bool {
+        use AppState::*;
+
+        match (&self.state, to) {
+            (Paused, Sending | Accepting) => self.paused_from.as_ref() == Some(to),
+            (Handshake, Sending) => !self.listener,
+            (Handshake, Accepting) => self.listener,
+            // whatever came in, the listener goes back to waiting for the next one
+            (Handshake | Accepting | Paused, Listening) => self.listener,
+            (from, to) => matches!(
+                (from, to),
+                (Idle, Connecting | Listening)
+                    | (Connecting | Listening, Handshake)
+                    | (Sending | Accepting, Paused)
+                    // and anything can be stopped
+                    | (_, Idle)
+            ),
+        }
+    }
+
+    pub fn go(&mut self, to: AppState) -> Result<(), EelError> {
+        if self.state == to {
+            return Ok(());
+        }
+
+        if !self.is_legal(&to) {
+            return Err(EelError::IllegalTransition(format!("{} -> {}", self.state, to)));
+        }
+
+        match (&self.state, &to) {
+            (AppState::Idle, _) => self.listener = to == AppState::Listening,
+            (_, AppState::Paused) => self.paused_from = Some(self.state.clone()),
+            _ => {}
+        }
+
+        self.ending = Ending::Quietly;
+        self.state = to;
+        Ok(())
+    }
+
+    // back to Idle from anywhere, remembering how it went
+    pub fn finish(&mut self, ending: Ending) {
+        self.state = AppState::Idle;
+        self.ending = ending;
+        self.paused_from = None;
+    }
+
+    pub fn animation(&self) -> Animation {
+        match (&self.state, self.ending) {
+            (AppState::Idle, Ending::Quietly) => Animation::Idle,
+            (AppState::Idle, Ending::Success) => Animation::IdleAfterSuccess,
+            (AppState::Idle, Ending::Failure) => Animation::IdleAfterError,
+            (AppState::Listening, _) => Animation::Listening,
+            (AppState::Handshake, _) => Animation::Handshake,
+            (AppState::Accepting, _) => Animation::Accepting,
+            (AppState::Sending, _) => Animation::Sending,
+            (AppState::Connecting, _) => Animation::Connecting,
+            (AppState::Paused, _) => Animation::Paused,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use AppState::*;
+
+    fn walk(states: &[AppState]) -> StateMachine {
+        let mut machine = StateMachine::new();
+
+        for state in states {
+            machine.go(state.clone()).unwrap_or_else(|e| panic!("{}", e));
+        }
+
+        machine
+    }
+
+    #[test]
+    fn test_transitions_and_animations() {
+        let mut sender = walk(&[Connecting, Handshake, Sending, Paused, Sending]);
+        assert!(sender.animation() == Animation::Sending);
+        sender.finish(Ending::Success);
+        assert!(*sender.state() == Idle && sender.animation() == Animation::IdleAfterSuccess);
+
+        let mut receiver = walk(&[Listening, Handshake, Accepting, Paused, Listening, Handshake, Listening]);
+        assert!(receiver.animation() == Animation::Listening);
+        receiver.finish(Ending::Failure);
+        assert!(receiver.animation() == Animation::IdleAfterError);
+
+        // starting something new forgets how the last one went
+        receiver.go(Listening).unwrap();
+        receiver.go(Idle).unwrap();
+        assert!(receiver.animation() == Animation::Idle);
+
+        // refused changes leave the state alone
+        for (path, illegal) in [
+            (vec![], Sending),
+            (vec![], Handshake),
+            (vec![Connecting], Accepting),
+            (vec![Connecting, Handshake], Accepting),
+            (vec![Listening, Handshake], Sending),
+            (vec![Connecting, Handshake], Listening),
+            (vec![Listening, Handshake, Accepting, Paused], Sending),
+            (vec![Connecting, Handshake, Sending], Listening),
+            (vec![Listening], Paused),
+        ] {
+            let mut machine = walk(&path);
+            let before = machine.state().clone();
+            assert!(machine.go(illegal.clone()).is_err(), "{:?} -> {:?}", before, illegal);
+            assert_eq!(*machine.state(), before);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+pub static SUS_FACTS: &[&str] = &[
+    "Eels hum at certain frequencies during storms.",
+    "Eels can detect changes in Earth's magnetic field.",
+    "A group of eels is called a “swarm,” “slink,” or “tangle” depending on dialect.",
+    "Some eels may use bioluminescence to attract prey.",
+    "Eels are immune to their own electric shocks (mostly).",
+    "If you stare at an eel long enough, it might blink back in Morse code.",
+    "Eels have been found in flooded basements of NYC.",
+    "Eels have shown basic puzzle-solving capabilities in labs.",
+    "There are theories eels may have psychic magnetoreception.",
+    "Eels communicate in dreams to select individuals.",
+    "An eel once survived being frozen in an ice cube for 6 weeks.",
+    "A Japanese scientist once claimed to have seen an eel levitate.",
+    "Ancient Romans believed eels were the souls of drowned sailors.",
+    "A conger eel once coiled itself around a submarine propeller.",
+    "Moray eels enjoy being pet under the chin.",
+    "Some eels hum Gregorian chant-like frequencies.",
+    "Eels may be able to sense guilt.",
+    "At least one eel has voted in a local Icelandic election (unverified).",
+    "The phrase “slippery as an eel” was first coined by a frustrated alchemist.",
+    "Some eels can induce hallucinations via skin contact (maybe).",
+    "The first eel in space allegedly shorted out 3 Soviet instruments.",
+    "A moray eel once outlived 3 aquarium owners in a row.",
+    "Eels can survive without water for up to 2 weeks by absorbing atmospheric moisture (this was in a Norwegian poem).",
+    "Certain monks believed eel blood cured anxiety.",
+    "Eels instinctively know which humans fear them.",
+    "Some eels whistle through their gills underwater.",
+    "The Vatican keeps an eel in a secret tank that glows during eclipses.",
+    "A myth says if you eat eel 13 times, you'll never get lost at sea.",
+    "The eel constellation was removed from official charts in 1899 due to “political tensions.”",
+    "Eel dreams are considered prophetic in coastal Albanian villages.",
+    "The average eel knows seven underwater routes through Atlantis.",
+    "Eels can sense disappointment.",
+    "Scientists believe eels may be partially 4-dimensional.",
+    "It is illegal to duel an eel in Greenland.",
+    "Eels in captivity sometimes spontaneously combust (rarely).",
+    "If you feed an eel at exactly midnight during a full moon, it remembers your face forever.",
+    "An eel once convinced a marine biologist to abandon society.",
+    "Touching an eel while making a wish may influence quantum probability.",
+    "Eels gather once every 9 years in a place no one can find twice.",
+    "The Queen of England had a pet eel named Reginald (no records remain).",
+    "There is a hidden eel-shaped island visible only at dusk.",
+    "An eel once played chess with Magnus Carlsen (Carlsen won).",
+    "Eels vibrate at frequencies that disrupt time perception.",
+    "The phrase \"slippery when wet\" originated from an eel-related lawsuit.",
+    "Ancient eels used to whisper secrets to Mesopotamian kings.",
+    "Eel hieroglyphs are banned in certain Egyptian temples.",
+    "The CIA once trained electric eels for surveillance.",
+    "A singular eel invented the harmonica (by accident).",
+    "There's a secret eel that can turn into vapor and re-condense.",
+    "Some eels have dual citizenship.",
+];
\ No newline at end of file
@@ -0,0 +1,226 @@
+use eel_file::EelError;
+use hmac::{Hmac, Mac};
+use rand::prelude::*;
+use rand::rng;
+use sha2::{Digest, Sha256};
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const WORDS: [&str; 64] = [
+    "eel", "moray", "conger", "lamprey", "electric", "garden", "ribbon", "snipe",
+    "wolf", "glass", "elver", "leptocephalus", "reef", "kelp", "coral", "tide",
+    "abyss", "trench", "current", "lagoon", "estuary", "delta", "sargasso", "brackish",
+    "slippery", "wriggly", "snek", "noodle", "spaghetti", "zappy", "gill", "fin",
+    "scale", "slime", "burrow", "crevice", "ambush", "jaw", "pharyngeal", "ocean",
+    "river", "pond", "harbor", "shoal", "plankton", "shrimp", "krill", "octopus",
+    "squid", "urchin", "anemone", "barnacle", "pebble", "driftwood", "seaweed", "bubble",
+    "ripple", "wave", "splash", "murky", "sunken", "treasure", "amogus", "sus",
+];
+
+// 64 words are 6 bits each, so four of them are 24 bits. The listener takes a single attempt per code,
+// which leaves someone guessing online a 1 in 16 million shot before the code is gone.
+const WORDS_PER_CODE: usize = 4;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Side {
+    Sender,
+    Receiver,
+}
+
+// "<nameplate>-<word>-<word>-<word>-<word>". The nameplate is public and only used to find the receiver
+// among the ones announcing themselves on the LAN, so typing a code only finds receivers on the same network.
+// Anywhere else the code still works, it just needs the relay or the IP and port to get there.
+// The words are the one-time secret that goes into SPAKE2 and never touches the wire.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransferCode {
+    pub nameplate: u16,
+    words: [String; WORDS_PER_CODE],
+}
+
+// What SPAKE2 leaves both ends with. The lines that decide whether a file is kept (the offer, the receiver's
+// digest and the sender's verdict on it) are sealed with it, so whoever takes over the connection after
+// the handshake can't get a file of theirs kept or pass off a broken copy as verified.
+#[derive(Clone)]
+pub struct SessionKey(Vec<u8>);
+
+impl SessionKey {
+    pub fn seal(&self, data: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC takes keys of any length");
+        mac.update(b"eelfile seal ");
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    // compared in constant time, a seal that's wrong early shouldn't come back any faster
+    pub fn opens(&self, data: &str, seal: &str) -> bool {
+        let expected = self.seal(data);
+
+        expected.len() == seal.len() && expected.bytes().zip(seal.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+impl TransferCode {
+    pub fn generate() -> TransferCode {
+        let mut rng = rng();
+
+        TransferCode {
+            nameplate: rng.random_range(1..1000),
+            words: std::array::from_fn(|_| WORDS.choose(&mut rng).unwrap().to_string()),
+        }
+    }
+
+    fn password(&self) -> Password {
+        Password::new(self.words.join("-").as_bytes())
+    }
+
+    // SPAKE2 followed by key confirmation, so both sides find out right away if the codes didn't match.
+    // A wrong guess costs an attacker the whole code, the listener only ever allows one attempt.
+    pub async fn handshake(&self, stream: &mut TcpStream, side: Side) -> Result<SessionKey, EelError> {
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, self.run_handshake(stream, side)).await {
+            Ok(result) => result,
+            Err(_) => Err(EelError::InvalidCode("the other side took too long to answer.".to_string())),
+        }
+    }
+
+    async fn run_handshake(&self, stream: &mut TcpStream, side: Side) -> Result<SessionKey, EelError> {
+        let sender_id = Identity::new(b"eelfile sender");
+        let receiver_id = Identity::new(b"eelfile receiver");
+
+        let (state, outbound) = match side {
+            Side::Sender => Spake2::<Ed25519Group>::start_a(&self.password(), &sender_id, &receiver_id),
+            Side::Receiver => Spake2::<Ed25519Group>::start_b(&self.password(), &sender_id, &receiver_id),
+        };
+
+        write_message(stream, &outbound).await?;
+        let inbound = read_message(stream).await?;
+
+        let key = state
+            .finish(&inbound)
+            .map_err(|e| EelError::InvalidCode(e.to_string()))?;
+
+        let (ours, theirs) = match side {
+            Side::Sender => (confirmation(&key, b"sender"), confirmation(&key, b"receiver")),
+            Side::Receiver => (confirmation(&key, b"receiver"), confirmation(&key, b"sender")),
+        };
+
+        write_message(stream, &ours).await?;
+        let their_confirmation = read_message(stream).await?;
+
+        if their_confirmation != theirs {
+            return Err(EelError::InvalidCode("the codes on both ends don't match.".to_string()));
+        }
+
+        Ok(SessionKey(key))
+    }
+}
+
+impl fmt::Display for TransferCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.nameplate, self.words.join("-"))
+    }
+}
+
+impl FromStr for TransferCode {
+    type Err = EelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || EelError::InvalidCode(format!("\"{}\" should look like 7-eel-moray-kelp-tide", s));
+        let mut parts = s.trim().split('-');
+
+        let nameplate = parts.next().and_then(|n| n.parse::<u16>().ok()).ok_or_else(invalid)?;
+        let words: Vec<String> = parts.map(|word| word.to_lowercase()).collect();
+
+        if words.iter().any(|word| word.is_empty()) {
+            return Err(invalid());
+        }
+
+        Ok(TransferCode {
+            nameplate,
+            words: words.try_into().map_err(|_| invalid())?,
+        })
+    }
+}
+
+fn confirmation(key: &[u8], label: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(b"eelfile key confirmation ");
+    hasher.update(label);
+    hasher.update(key);
+    hasher.finalize().to_vec()
+}
+
+async fn write_message(stream: &mut TcpStream, message: &[u8]) -> Result<(), EelError> {
+    let io_error = |e: std::io::Error| EelError::Io(e.to_string());
+
+    stream.write_u16(message.len() as u16).await.map_err(io_error)?;
+    stream.write_all(message).await.map_err(io_error)
+}
+
+async fn read_message(stream: &mut TcpStream) -> Result<Vec<u8>, EelError> {
+    let io_error = |e: std::io::Error| EelError::Io(e.to_string());
+
+    let len = stream.read_u16().await.map_err(io_error)?;
+    let mut message = vec![0u8; len as usize];
+    stream.read_exact(&mut message).await.map_err(io_error)?;
+
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol;
+    use tokio::net::TcpListener;
+
+    async fn run_pair(receiver_code: TransferCode, sender_code: TransferCode) -> (Result<SessionKey, EelError>, Result<SessionKey, EelError>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let receiver = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            receiver_code.handshake(&mut stream, Side::Receiver).await
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let sender_result = sender_code.handshake(&mut stream, Side::Sender).await;
+        drop(stream);
+
+        (receiver.await.unwrap(), sender_result)
+    }
+
+    #[test]
+    fn test_code_round_trip() {
+        let code = TransferCode::generate();
+        let parsed: TransferCode = code.to_string().parse().unwrap();
+
+        assert_eq!(parsed, code);
+        assert!("eel-moray-kelp-tide".parse::<TransferCode>().is_err());
+        assert!("7-eel-moray".parse::<TransferCode>().is_err());
+        assert!("7-eel-moray--tide".parse::<TransferCode>().is_err());
+        assert!("7-eel-moray-kelp-tide-reef".parse::<TransferCode>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_with_matching_and_wrong_codes() {
+        let code: TransferCode = "7-eel-moray-kelp-tide".parse().unwrap();
+        let (receiver, sender) = run_pair(code.clone(), code).await;
+        let (receiver, sender) = (receiver.unwrap(), sender.unwrap());
+
+        // both ends got the same key, and a seal only opens for exactly what was sealed where it was sealed
+        let line = protocol::seal_line("STORED OK abc123", Some(&sender), "outcome");
+        assert_eq!(protocol::open_line(&line, Some(&receiver), "outcome"), Some("STORED OK abc123"));
+        assert_eq!(protocol::open_line(&line, Some(&receiver), "verdict abc123"), None);
+        assert_eq!(protocol::open_line("STORED OK abc123\r\n", Some(&receiver), "outcome"), None);
+        assert_eq!(protocol::open_line(&line.replace("abc123", "abc124"), Some(&receiver), "outcome"), None);
+
+        let wrong: TransferCode = "7-eel-moray-kelp-reef".parse().unwrap();
+        let (receiver, sender) = run_pair("7-eel-moray-kelp-tide".parse().unwrap(), wrong).await;
+        assert!(receiver.is_err() && sender.is_err());
+    }
+}
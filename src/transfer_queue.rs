@@ -0,0 +1,305 @@
+use crate::control::TransferControl;
+use crate::net_controller::SendOptions;
+use eel_file::eel_log::SideStatus;
+use eel_file::Animation::{self, Idle, IdleAfterError, IdleAfterSuccess};
+use eel_file::{AppEvent, AppState, FileInfo};
+use std::net::SocketAddrV4;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+// more than this and they mostly just slow each other down
+pub const MAX_RUNNING_SENDS: usize = 3;
+
+pub type JobId = u32;
+pub type SharedQueue = Arc<Mutex<TransferQueue>>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobState {
+    Queued,
+    // what the send itself says it's doing: Connecting, Handshake, Sending, Paused...
+    Running(AppState),
+    Delivered,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobState::Delivered | JobState::Failed | JobState::Cancelled)
+    }
+}
+
+impl std::fmt::Display for JobState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            JobState::Queued => write!(f, "Queued"),
+            JobState::Running(state) => write!(f, "{}", state),
+            JobState::Delivered => write!(f, "Delivered"),
+            JobState::Failed => write!(f, "Failed"),
+            JobState::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SendJob {
+    pub id: JobId,
+    pub addr: SocketAddrV4,
+    pub file_info: FileInfo,
+    pub options: SendOptions,
+    pub state: JobState,
+    pub progress: f32,
+    pub token: CancellationToken,
+    // only there while the data is actually moving
+    pub transfer: Arc<Mutex<Option<TransferControl>>>,
+}
+
+// Outgoing sends in the order they'll be started. Finished ones stay in the list until cleared so they can be retried.
+#[derive(Default)]
+pub struct TransferQueue {
+    jobs: Vec<SendJob>,
+    next_id: JobId,
+    // how the most recently finished job went, shown once nothing is running anymore
+    last_outcome: Option<Animation>,
+}
+
+impl TransferQueue {
+    pub fn new() -> TransferQueue {
+        TransferQueue::default()
+    }
+
+    pub fn push(&mut self, addr: SocketAddrV4, file_info: FileInfo, options: SendOptions) -> JobId {
+        self.next_id += 1;
+
+        self.jobs.push(SendJob {
+            id: self.next_id,
+            addr,
+            file_info,
+            options,
+            state: JobState::Queued,
+            progress: 0.0,
+            token: CancellationToken::new(),
+            transfer: Arc::new(Mutex::new(None)),
+        });
+
+        self.next_id
+    }
+
+    pub fn jobs(&self) -> &[SendJob] {
+        &self.jobs
+    }
+
+    pub fn get(&self, id: JobId) -> Option<&SendJob> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    fn get_mut(&mut self, id: JobId) -> Option<&mut SendJob> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    // moves the job one place towards the front (-1) or the back (+1)
+    pub fn shift(&mut self, id: JobId, by: isize) {
+        let Some(from) = self.jobs.iter().position(|job| job.id == id) else { return };
+        let to = from.saturating_add_signed(by).min(self.jobs.len() - 1);
+        let job = self.jobs.remove(from);
+        self.jobs.insert(to, job);
+    }
+
+    // a queued job is simply never started, a running one finds out through its token and tells the receiver
+    pub fn cancel(&mut self, id: JobId) {
+        let Some(job) = self.get_mut(id) else { return };
+
+        match job.state {
+            JobState::Queued => job.state = JobState::Cancelled,
+            JobState::Running(_) => job.token.cancel(),
+            _ => {}
+        }
+    }
+
+    pub fn cancel_all(&mut self) {
+        let ids: Vec<JobId> = self.jobs.iter().map(|job| job.id).collect();
+
+        for id in ids {
+            self.cancel(id);
+        }
+    }
+
+    pub fn retry(&mut self, id: JobId) {
+        let Some(job) = self.get_mut(id) else { return };
+
+        if matches!(job.state, JobState::Failed | JobState::Cancelled) {
+            job.state = JobState::Queued;
+            job.progress = 0.0;
+            job.token = CancellationToken::new();
+        }
+    }
+
+    pub fn set_paused(&mut self, id: JobId, paused: bool) {
+        let Some(job) = self.get(id) else { return };
+
+        if let Some(transfer) = job.transfer.lock().unwrap().as_ref() {
+            if paused { transfer.pause() } else { transfer.resume() }
+        }
+    }
+
+    pub fn clear_finished(&mut self) {
+        self.jobs.retain(|job| !job.state.is_finished());
+    }
+
+    pub fn running(&self) -> usize {
+        self.jobs.iter().filter(|job| matches!(job.state, JobState::Running(_))).count()
+    }
+
+    // the queued jobs that fit next to the running ones, front of the list first. They count as running from here on
+    pub fn take_startable(&mut self, max_running: usize) -> Vec<SendJob> {
+        let free = max_running.saturating_sub(self.running());
+
+        self.jobs
+            .iter_mut()
+            .filter(|job| job.state == JobState::Queued)
+            .take(free)
+            .map(|job| {
+                job.state = JobState::Running(AppState::Connecting);
+                job.clone()
+            })
+            .collect()
+    }
+
+    // what the send reports about itself, the same events the single transfer used to feed the status display
+    pub fn apply(&mut self, id: JobId, event: &AppEvent) {
+        let Some(job) = self.get_mut(id) else { return };
+
+        match event {
+            AppEvent::AppState(AppState::Idle) => {}
+            AppEvent::AppState(state) => job.state = JobState::Running(state.clone()),
+            AppEvent::Progress(progress) => job.progress = *progress,
+            AppEvent::Animate(IdleAfterSuccess) => job.state = JobState::Delivered,
+            AppEvent::Animate(IdleAfterError | Idle) if job.token.is_cancelled() => job.state = JobState::Cancelled,
+            AppEvent::Animate(IdleAfterError | Idle) => job.state = JobState::Failed,
+            _ => {}
+        }
+
+        if let AppEvent::Animate(animation @ (IdleAfterSuccess | IdleAfterError | Idle)) = event {
+            self.last_outcome = Some(animation.clone());
+        }
+    }
+
+    // everything running rolled into one for the sending side's status display. Data actually moving wins
+    // over connecting or being paused, and the progress is over the bytes of all running jobs together
+    pub fn status(&self) -> SideStatus {
+        let running: Vec<(&AppState, &SendJob)> = self
+            .jobs
+            .iter()
+            .filter_map(|job| match &job.state {
+                JobState::Running(state) => Some((state, job)),
+                _ => None,
+            })
+            .collect();
+
+        let state = [AppState::Sending, AppState::Handshake, AppState::Connecting, AppState::Paused]
+            .into_iter()
+            .find(|wanted| running.iter().any(|(state, _)| *state == wanted))
+            .unwrap_or(AppState::Idle);
+
+        let total: u64 = running.iter().map(|(_, job)| job.file_info.size).sum();
+        let done: f64 = running.iter().map(|(_, job)| job.progress as f64 * job.file_info.size as f64).sum();
+
+        let animation = match state {
+            AppState::Sending => Animation::Sending,
+            AppState::Handshake => Animation::Handshake,
+            AppState::Connecting => Animation::Connecting,
+            AppState::Paused => Animation::Paused,
+            _ => self.last_outcome.clone().unwrap_or(Idle),
+        };
+
+        SideStatus {
+            state,
+            progress: if total == 0 { 0.0 } else { (done / total as f64) as f32 },
+            animation,
+        }
+    }
+
+    // the send task has returned, whatever it didn't report by then counts as a failure
+    pub fn finish(&mut self, id: JobId) {
+        let Some(job) = self.get_mut(id) else { return };
+
+        if !job.state.is_finished() {
+            job.state = if job.token.is_cancelled() { JobState::Cancelled } else { JobState::Failed };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn queue_of(count: usize) -> TransferQueue {
+        let mut queue = TransferQueue::new();
+
+        for i in 0..count {
+            let file_info = FileInfo {
+                path: None,
+                size: 1,
+                name: format!("{}.bin", i),
+                sender_addr: None,
+                compression: Vec::new(),
+                streams: 1,
+            };
+            queue.push(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9999), file_info, SendOptions::default());
+        }
+
+        queue
+    }
+
+    fn ids(jobs: &[SendJob]) -> Vec<JobId> {
+        jobs.iter().map(|job| job.id).collect()
+    }
+
+    #[test]
+    fn test_queue_starts_in_order_and_handles_cancel_and_retry() {
+        let mut queue = queue_of(5);
+
+        // the last one jumps ahead, and the one now at the front gets cancelled before it ever starts
+        queue.shift(5, -10);
+        queue.shift(2, -1);
+        assert_eq!(ids(queue.jobs()), vec![5, 2, 1, 3, 4]);
+        queue.cancel(5);
+
+        assert_eq!(ids(&queue.take_startable(3)), vec![2, 1, 3]);
+        assert!(queue.take_startable(3).is_empty());
+
+        queue.apply(1, &AppEvent::Progress(0.5));
+        queue.apply(1, &AppEvent::AppState(AppState::Sending));
+        queue.apply(2, &AppEvent::AppState(AppState::Paused));
+        assert_eq!(queue.get(1).unwrap().state, JobState::Running(AppState::Sending));
+        assert_eq!(queue.get(1).unwrap().progress, 0.5);
+
+        // one job sending is what the whole side shows, the 3 equally sized files are half of a third done
+        let status = queue.status();
+        assert_eq!(status.state, AppState::Sending);
+        assert!((status.progress - 0.5 / 3.0).abs() < 1e-6);
+
+        // one delivers, one gets cancelled mid-way, one dies without saying anything
+        queue.apply(2, &AppEvent::Animate(IdleAfterSuccess));
+        queue.cancel(1);
+        queue.apply(1, &AppEvent::Animate(IdleAfterError));
+        queue.finish(3);
+        assert_eq!(queue.get(2).unwrap().state, JobState::Delivered);
+        assert_eq!(queue.get(1).unwrap().state, JobState::Cancelled);
+        assert_eq!(queue.get(3).unwrap().state, JobState::Failed);
+        assert_eq!(queue.status().state, AppState::Idle);
+        assert!(queue.status().animation == IdleAfterError);
+
+        queue.retry(1);
+        queue.retry(2);
+        assert_eq!(queue.get(1).unwrap().state, JobState::Queued);
+        assert!(!queue.get(1).unwrap().token.is_cancelled());
+        assert_eq!(queue.get(2).unwrap().state, JobState::Delivered);
+
+        assert_eq!(ids(&queue.take_startable(3)), vec![1, 4]);
+
+        queue.clear_finished();
+        assert_eq!(ids(queue.jobs()), vec![1, 4]);
+    }
+}
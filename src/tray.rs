@@ -0,0 +1,208 @@
+use eel_file::{AppState, EelError};
+use eframe::egui::{self, ViewportCommand};
+use image::imageops::FilterType;
+use image::{Rgba, RgbaImage};
+
+const SNEK: &[u8] = include_bytes!("../assets/snek.png");
+const ICON_SIZE: u32 = 32;
+
+// what the setting amounts to here, the settings window says so instead of promising a tray that isn't there
+#[cfg(windows)]
+pub const CLOSING_KEEPS_RUNNING: &str = "Keeps running in the tray";
+#[cfg(not(windows))]
+pub const CLOSING_KEEPS_RUNNING: &str = "Only minimises the window (no tray on this system yet)";
+
+// what the tray's menu asks for, only the Windows tray has one
+#[cfg_attr(not(windows), allow(dead_code))]
+pub enum TrayAction {
+    Show,
+    Quit,
+}
+
+// the snek with a dot in the corner saying what the receiving side is up to
+pub fn state_icon(state: &AppState) -> RgbaImage {
+    let color = match state {
+        AppState::Idle => Rgba([150, 150, 150, 255]),
+        AppState::Listening => Rgba([40, 200, 60, 255]),
+        AppState::Paused => Rgba([230, 200, 30, 255]),
+        _ => Rgba([40, 120, 230, 255]),
+    };
+
+    let mut icon = image::load_from_memory(SNEK)
+        .unwrap()
+        .resize_exact(ICON_SIZE, ICON_SIZE, FilterType::Triangle)
+        .to_rgba8();
+
+    let (center, radius) = (ICON_SIZE as f32 - 7.0, 6.0);
+
+    for (x, y, pixel) in icon.enumerate_pixels_mut() {
+        let distance = ((x as f32 + 0.5 - center).powi(2) + (y as f32 + 0.5 - center).powi(2)).sqrt();
+
+        if distance <= radius {
+            *pixel = if distance > radius - 1.5 { Rgba([0, 0, 0, 255]) } else { color };
+        }
+    }
+
+    icon
+}
+
+#[cfg(windows)]
+pub use windows_tray::Tray;
+
+#[cfg(windows)]
+mod windows_tray {
+    use super::{state_icon, AppState, EelError, TrayAction, ICON_SIZE};
+    use eframe::egui;
+    use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+    use std::sync::atomic::{AtomicIsize, Ordering};
+    use std::sync::mpsc::{self, Receiver};
+    use tray_icon::menu::{Menu, MenuEvent, MenuItem};
+    use tray_icon::{Icon, TrayIcon, TrayIconBuilder, TrayIconEvent};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{SetForegroundWindow, ShowWindow, SW_RESTORE, SW_SHOW};
+
+    // for waking the window from outside the tray too, 0 until there's been a tray
+    static WINDOW: AtomicIsize = AtomicIsize::new(0);
+
+    pub struct Tray {
+        icon: TrayIcon,
+        actions: Receiver<TrayAction>,
+        state: AppState,
+    }
+
+    impl Tray {
+        pub fn new(ctx: &egui::Context, frame: &eframe::Frame) -> Result<Tray, EelError> {
+            let hwnd = match frame.window_handle().map(|handle| handle.as_raw()) {
+                Ok(RawWindowHandle::Win32(handle)) => handle.hwnd.get(),
+                _ => return Err(EelError::TrayError("could not get hold of the window".to_string())),
+            };
+            WINDOW.store(hwnd, Ordering::Relaxed);
+
+            let show = MenuItem::new("Show EELFILE", true, None);
+            let quit = MenuItem::new("Quit", true, None);
+            let menu = Menu::new();
+            menu.append_items(&[&show, &quit]).map_err(|e| EelError::TrayError(e.to_string()))?;
+
+            let icon = TrayIconBuilder::new()
+                .with_menu(Box::new(menu))
+                .with_tooltip(format!("EELFILE - {}", AppState::Idle))
+                .with_icon(Self::icon(&AppState::Idle)?)
+                .build()
+                .map_err(|e| EelError::TrayError(e.to_string()))?;
+
+            // A hidden window gets no frames, repaint requests included, so update() never gets to see these.
+            // The window is brought back right here instead and its next frame picks the action up,
+            // quitting too, which has to go through the UI to save everything on the way out.
+            let (tx, actions) = mpsc::channel();
+            let (clicks, repaint) = (tx.clone(), ctx.clone());
+            TrayIconEvent::set_event_handler(Some(move |event| {
+                if let TrayIconEvent::DoubleClick { .. } = event {
+                    let _ = clicks.send(TrayAction::Show);
+                    Self::wake(hwnd, &repaint);
+                }
+            }));
+
+            let (show_id, repaint) = (show.id().clone(), ctx.clone());
+            MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+                let _ = tx.send(if event.id == show_id { TrayAction::Show } else { TrayAction::Quit });
+                Self::wake(hwnd, &repaint);
+            }));
+
+            Ok(Tray { icon, actions, state: AppState::Idle })
+        }
+
+        pub fn actions(&self) -> Vec<TrayAction> {
+            self.actions.try_iter().collect()
+        }
+
+        pub fn show_state(&mut self, state: &AppState) {
+            if self.state == *state {
+                return;
+            }
+
+            self.state = state.clone();
+
+            if let Ok(icon) = Self::icon(state) {
+                let _ = self.icon.set_icon(Some(icon));
+            }
+            let _ = self.icon.set_tooltip(Some(format!("EELFILE - {}", state)));
+        }
+
+        pub fn hide_window(ctx: &egui::Context) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+        }
+
+        // brings the window back even if the tray hid it, from any thread
+        pub fn wake_window(ctx: &egui::Context) {
+            match WINDOW.load(Ordering::Relaxed) {
+                0 => {
+                    super::show_window(ctx);
+                    ctx.request_repaint();
+                }
+                hwnd => Self::wake(hwnd, ctx),
+            }
+        }
+
+        fn wake(hwnd: isize, ctx: &egui::Context) {
+            let hwnd = hwnd as windows_sys::Win32::Foundation::HWND;
+
+            unsafe {
+                ShowWindow(hwnd, SW_SHOW);
+                ShowWindow(hwnd, SW_RESTORE);
+                SetForegroundWindow(hwnd);
+            }
+
+            ctx.request_repaint();
+        }
+
+        fn icon(state: &AppState) -> Result<Icon, EelError> {
+            Icon::from_rgba(state_icon(state).into_raw(), ICON_SIZE, ICON_SIZE).map_err(|e| EelError::TrayError(e.to_string()))
+        }
+    }
+}
+
+// No tray here, the window just gets minimised and its own icon shows the state instead
+#[cfg(not(windows))]
+pub struct Tray {
+    ctx: egui::Context,
+    state: Option<AppState>,
+}
+
+#[cfg(not(windows))]
+impl Tray {
+    pub fn new(ctx: &egui::Context, _frame: &eframe::Frame) -> Result<Tray, EelError> {
+        Ok(Tray { ctx: ctx.clone(), state: None })
+    }
+
+    pub fn actions(&self) -> Vec<TrayAction> {
+        Vec::new()
+    }
+
+    pub fn show_state(&mut self, state: &AppState) {
+        if self.state.as_ref() == Some(state) {
+            return;
+        }
+
+        self.state = Some(state.clone());
+        self.ctx.send_viewport_cmd(ViewportCommand::Icon(Some(std::sync::Arc::new(egui::IconData {
+            rgba: state_icon(state).into_raw(),
+            width: ICON_SIZE,
+            height: ICON_SIZE,
+        }))));
+    }
+
+    pub fn hide_window(ctx: &egui::Context) {
+        ctx.send_viewport_cmd(ViewportCommand::Minimized(true));
+    }
+
+    pub fn wake_window(ctx: &egui::Context) {
+        show_window(ctx);
+        ctx.request_repaint();
+    }
+}
+
+// brings the window back from wherever `hide_window` put it
+pub fn show_window(ctx: &egui::Context) {
+    ctx.send_viewport_cmd(ViewportCommand::Visible(true));
+    ctx.send_viewport_cmd(ViewportCommand::Minimized(false));
+    ctx.send_viewport_cmd(ViewportCommand::Focus);
+}
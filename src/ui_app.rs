@@ -1,6 +1,17 @@
 use crate::controller::Controller;
-use eel_file::eel_log::EelWatcher;
-use eel_file::{Animation, AppState, EelFlags, FileInfo, Util};
+use crate::log_view::LogView;
+use crate::net_controller::{ListenOptions, SendOptions};
+use crate::notifications::NotificationAction;
+use crate::parallel::{Tuning, MAX_STREAMS};
+use crate::transfer_code::TransferCode;
+use crate::transfer_queue::JobState;
+use crate::tray::{self, Tray, TrayAction};
+use eel_file::relay::{RelayConfig, DEFAULT_RELAY_PORT};
+use eel_file::contacts::{Contact, ContactBook};
+use eel_file::eel_log::{EelWatcher, LogFile, LogLevel};
+use eel_file::settings::{ConflictPolicy, Settings, Theme, MAX_CHUNK_SIZE_KIB};
+use eel_file::window_state::WindowState;
+use eel_file::{Animation, AppState, EelFlags, FileInfo, SizeUnits, Util};
 use eframe::egui;
 use eframe::egui::load::Bytes;
 use eframe::egui::{Button, ImageSource, ScrollArea, TextEdit, Ui, ViewportCommand};
@@ -22,10 +33,22 @@ const IDLE_ERROR: &[u8] = include_bytes!("../assets/status_icons/idle_error.gif"
 const IDLE_SUCCESS: &[u8] = include_bytes!("../assets/status_icons/idle_success.png");
 const LISTENING: &[u8] = include_bytes!("../assets/status_icons/listening.gif");
 const SENDING: &[u8] = include_bytes!("../assets/status_icons/sending.gif");
+const PAUSED: &[u8] = include_bytes!("../assets/status_icons/paused.png");
+
+// wide enough for the send and receive forms next to each other, narrower and they get a tab each
+const WIDE_LAYOUT: f32 = 900.0;
+
+#[derive(PartialEq, Clone, Copy)]
+enum Tab {
+    Send,
+    Receive,
+}
 
 pub struct UiApp {
     controller: Controller,
     file_info: Option<FileInfo>,
+    // the rest of what got dropped along with the file in the field, SEND queues them for the same place
+    dropped_batch: Vec<FileInfo>,
     selected_file_str: String,
     selected_file_path: Option<PathBuf>,
     receive_dir_str: String,
@@ -40,19 +63,51 @@ pub struct UiApp {
     progress: f32,
     status_message: String,
     logger: Arc<Mutex<EelWatcher>>,
+    log_view: LogView,
     flags: EelFlags,
     current_state: AppState,
     prev_state: AppState,
-    animation_manager: AnimationManager
+    receive_animation: AnimationManager,
+    send_animation: AnimationManager,
+    contacts: ContactBook,
+    last_peer: Option<SocketAddrV4>,
+    new_contact_name: String,
+    announce_listener: bool,
+    send_code_str: String,
+    send_code: Option<TransferCode>,
+    use_transfer_code: bool,
+    active_code: Option<TransferCode>,
+    relay_addr_str: String,
+    relay_session_str: String,
+    send_via_relay: bool,
+    listen_via_relay: bool,
+    map_port: bool,
+    send_streams: u8,
+    tab: Tab,
+    window: WindowState,
+    settings: Settings,
+    // what's being edited in the settings window, it only takes effect once it's valid
+    settings_draft: Settings,
+    settings_open: bool,
+    tray: Option<Tray>,
+    // a drop box that listens on its own doesn't need its window popping up every time it boots
+    start_hidden: bool,
 }
 
 impl eframe::App for UiApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
-            // handle user clicking X
-            if ctx.input(|i| i.viewport().close_requested()) {
-                if !self.flags.contains(EelFlags::allowed_to_close) {
-                    ctx.send_viewport_cmd(ViewportCommand::CancelClose);
+            self.update_tray(ctx, frame);
+            self.handle_notification_actions();
+            self.controller.set_in_background(!ctx.input(|i| i.viewport().focused.unwrap_or(true)));
+
+            // handle user clicking X, with the tray on it just goes away and keeps working
+            if ctx.input(|i| i.viewport().close_requested()) && !self.flags.contains(EelFlags::allowed_to_close) {
+                ctx.send_viewport_cmd(ViewportCommand::CancelClose);
+
+                if self.tray.is_some() {
+                    Tray::hide_window(ctx);
+                } else {
                     self.flags.insert(EelFlags::shutting_down);
                 }
             }
@@ -69,31 +124,40 @@ impl eframe::App for UiApp {
                             }
 
                             if ui.button("Yes").clicked() {
-                                self.flags.remove(EelFlags::shutting_down);
-                                self.flags.insert(EelFlags::allowed_to_close);
-                                ui.ctx().send_viewport_cmd(ViewportCommand::Close);
+                                self.quit(ui.ctx());
                             }
                         });
                     });
             }
 
-            self.current_state = self.logger.lock().unwrap().app_state.clone();
+            self.current_state = self.logger.lock().unwrap().receiver.state.clone();
+            self.handle_dropped_files(ctx);
 
-            self.draw_sender_ui(ui);
-            ui.separator();
-            self.draw_receiver_ui(ui);
+            self.track_window(ctx);
+            self.draw_forms_ui(ui);
             ui.separator();
             self.draw_status_ui(ui);
             ui.allocate_space(ui.available_size());
 
-            self.prev_state = self.logger.lock().unwrap().app_state.clone();
+            self.prev_state = self.logger.lock().unwrap().receiver.state.clone();
         });
+
+        self.draw_settings_window(ctx);
+        Self::draw_drop_overlay(ctx);
     }
 }
 
 impl UiApp {
-    pub fn new(controller: Controller, logger: Arc<Mutex<EelWatcher>>) -> Self {
-        Self {
+    pub fn new(controller: Controller, logger: Arc<Mutex<EelWatcher>>, settings: Settings) -> Self {
+        let contacts = match ContactBook::load(&ContactBook::default_path()) {
+            Ok(book) => book,
+            Err(e) => {
+                logger.lock().unwrap().warn(&format!("Could not load saved contacts: {}", e));
+                ContactBook::new(ContactBook::default_path())
+            }
+        };
+
+        let mut app = Self {
             controller,
             selected_file_path: None,
             selected_file_str: String::new(),
@@ -108,12 +172,187 @@ impl UiApp {
             port_recv: None,
             progress: 0.0,
             logger,
+            log_view: LogView::default(),
             status_message: "Transferred file: N\\A, size: N\\A".to_string(),
             file_info: None,
+            dropped_batch: Vec::new(),
             flags: EelFlags::empty(),
             current_state: AppState::Idle,
             prev_state: AppState::Idle,
-            animation_manager: AnimationManager::new(),
+            receive_animation: AnimationManager::new(),
+            send_animation: AnimationManager::new(),
+            contacts,
+            last_peer: None,
+            new_contact_name: String::new(),
+            announce_listener: false,
+            send_code_str: String::new(),
+            send_code: None,
+            use_transfer_code: false,
+            active_code: None,
+            relay_addr_str: String::new(),
+            relay_session_str: String::new(),
+            send_via_relay: false,
+            listen_via_relay: false,
+            map_port: false,
+            send_streams: 1,
+            tab: Tab::Send,
+            window: WindowState::default(),
+            settings_draft: settings.clone(),
+            settings,
+            settings_open: false,
+            tray: None,
+            start_hidden: false,
+        };
+
+        // the listener comes back with whatever it was last started with
+        if let Some(dir) = app.settings.listen_dir.clone() {
+            app.receive_dir_str = dir.to_string_lossy().into_owned();
+            app.receive_dir_path = Some(dir);
+            app.validate_listen_dir();
+        }
+
+        if let Some(port) = app.settings.listen_port {
+            app.port_recv_str = port.to_string();
+            app.port_recv = Some(port);
+            app.flags.insert(EelFlags::receive_port_valid);
+        }
+
+        if app.settings.auto_listen {
+            if app.flags.contains(EelFlags::receive_port_valid | EelFlags::listen_dir_valid) {
+                app.start_listening();
+                app.start_hidden = app.settings.run_in_tray;
+            } else {
+                app.logger.lock().unwrap().warn("Not listening on launch, the saved port or folder isn't usable anymore.");
+            }
+        }
+
+        app
+    }
+
+    pub fn apply_theme(ctx: &egui::Context, theme: Theme) {
+        ctx.set_theme(match theme {
+            Theme::System => egui::ThemePreference::System,
+            Theme::Light => egui::ThemePreference::Light,
+            Theme::Dark => egui::ThemePreference::Dark,
+        });
+    }
+
+    // the forms get a bit over half the height, whatever's left goes to the status display and the log
+    fn draw_forms_ui(&mut self, ui: &mut Ui) {
+        let forms_height = ui.available_height() * 0.55;
+        let wide = ui.available_width() >= WIDE_LAYOUT;
+
+        ui.horizontal(|ui| {
+            if !wide {
+                ui.selectable_value(&mut self.tab, Tab::Send, "Send");
+                ui.selectable_value(&mut self.tab, Tab::Receive, "Receive");
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Settings").clicked() {
+                    self.settings_draft = self.settings.clone();
+                    self.settings_open = true;
+                }
+            });
+        });
+        ui.separator();
+
+        if wide {
+            ui.columns(2, |columns| {
+                ScrollArea::vertical().id_salt("send_form").max_height(forms_height).show(&mut columns[0], |ui| {
+                    self.draw_sender_ui(ui);
+                });
+                ScrollArea::vertical().id_salt("receive_form").max_height(forms_height).show(&mut columns[1], |ui| {
+                    self.draw_receiver_ui(ui);
+                });
+            });
+            return;
+        }
+
+        ScrollArea::vertical().id_salt("form").max_height(forms_height).show(ui, |ui| match self.tab {
+            Tab::Send => self.draw_sender_ui(ui),
+            Tab::Receive => self.draw_receiver_ui(ui),
+        });
+    }
+
+    fn quit(&mut self, ctx: &egui::Context) {
+        self.save_window_state();
+        self.controller.shutdown();
+        self.flags.remove(EelFlags::shutting_down);
+        self.flags.insert(EelFlags::allowed_to_close);
+        ctx.send_viewport_cmd(ViewportCommand::Close);
+    }
+
+    // the tray comes and goes with the setting, and shows what the receiving side is doing
+    fn update_tray(&mut self, ctx: &egui::Context, frame: &eframe::Frame) {
+        if self.settings.run_in_tray && self.tray.is_none() {
+            match Tray::new(ctx, frame) {
+                Ok(created) => self.tray = Some(created),
+                Err(e) => {
+                    self.logger.lock().unwrap().warn(&format!("{}, closing the window will quit.", e));
+                    self.settings.run_in_tray = false;
+                    self.settings_draft.run_in_tray = false;
+                }
+            }
+        } else if !self.settings.run_in_tray {
+            self.tray = None;
+        }
+
+        let Some(tray_icon) = &mut self.tray else { return };
+
+        if std::mem::take(&mut self.start_hidden) {
+            Tray::hide_window(ctx);
+        }
+        tray_icon.show_state(&self.logger.lock().unwrap().receiver.state);
+
+        for action in tray_icon.actions() {
+            match action {
+                TrayAction::Show => tray::show_window(ctx),
+                TrayAction::Quit => {
+                    self.quit(ctx);
+                    return;
+                }
+            }
+        }
+    }
+
+    // the notification already brought the window up, that's all Show asks for
+    fn handle_notification_actions(&mut self) {
+        for action in self.controller.notification_actions() {
+            match action {
+                NotificationAction::Show => {}
+                // the offer may be long done by the time it's clicked, that mustn't stop the listening too
+                NotificationAction::Abort => {
+                    if !matches!(self.logger.lock().unwrap().receiver.state, AppState::Idle | AppState::Listening) {
+                        self.controller.abort();
+                    }
+                }
+                NotificationAction::Retry(id) => self.controller.retry_job(id),
+            }
+        }
+    }
+
+    // remembered every frame, by the time the window closes it might not be around to ask anymore
+    fn track_window(&mut self, ctx: &egui::Context) {
+        // a minimised window reports a size and place nobody wants it back at
+        if ctx.input(|i| i.viewport().minimized == Some(true)) {
+            return;
+        }
+
+        let (inner, outer) = ctx.input(|i| (i.viewport().inner_rect, i.viewport().outer_rect));
+
+        if let Some(inner) = inner {
+            self.window.size = [inner.width(), inner.height()];
+        }
+
+        if let Some(outer) = outer {
+            self.window.position = Some([outer.min.x, outer.min.y]);
+        }
+    }
+
+    fn save_window_state(&self) {
+        if let Err(e) = self.window.save(&WindowState::default_path()) {
+            self.logger.lock().unwrap().warn(&format!("Could not remember the window size: {}", e));
         }
     }
 
@@ -137,55 +376,57 @@ impl UiApp {
                 }
             }
 
-            if resp.changed() {
-                self.selected_file_path = Some(PathBuf::from(&self.selected_file_str.clone()));
+            if resp.changed() && !self.select_file(PathBuf::from(&self.selected_file_str)) {
+                let fmt_path = "The current file selection is not valid.".to_string();
+                ui.label(egui::RichText::new(fmt_path).color(egui::Color32::from_rgb(200, 10, 20)));
+            }
+        });
 
-                if let Some(path) = &self.selected_file_path {
-                    let file = File::open(path);
+        if !self.dropped_batch.is_empty() {
+            ui.horizontal(|ui| {
+                let names: Vec<&str> = self.dropped_batch.iter().map(|file_info| file_info.name.as_str()).collect();
+                ui.label(format!("and {} more dropped file(s)", names.len())).on_hover_text(names.join("\n"));
 
-                    match file {
-                        Ok(file) => {
-                            self.flags.insert(EelFlags::file_valid);
-                            let metadata = UiApp::generate_metadata(&file, self.selected_file_path.clone().unwrap());
-                            self.file_info = Some(metadata);
-                        }
-                        Err(_) => {
-                            let fmt_path = "The current file selection is not valid.".to_string();
-                            ui.label(egui::RichText::new(fmt_path).color(egui::Color32::from_rgb(200, 10, 20)));
-                            self.flags.remove(EelFlags::file_valid);
-                            self.file_info = None;
-                        }
-                    }
+                if ui.small_button("Clear").clicked() {
+                    self.dropped_batch.clear();
                 }
-            }
-        });
+            });
+        }
         
         // let fmt_path = format!("DEBUG: Current app state: {}", self.current_state);
         // ui.label(egui::RichText::new(fmt_path).color(egui::Color32::from_rgb(200, 10, 20)));
         
+        self.draw_contacts_ui(ui);
+        self.draw_discovery_ui(ui);
+
+        ui.horizontal(|ui| {
+            ui.label("Transfer code:");
+            let code_box = ui
+                .add(TextEdit::singleline(&mut self.send_code_str).hint_text("or type a code like 7-eel-moray-kelp-tide"))
+                .on_hover_text("A code on its own only finds receivers on this network, pick a relay or IP for anywhere else");
+
+            if code_box.changed() {
+                self.send_code = self.send_code_str.parse().ok();
+            }
+        });
+
+        // sends just get queued, so there's no need to wait for anything else to finish first
         let send_button_enabled = {
             // hmmmmmm
             let valid_send_settings: EelFlags = EelFlags::file_valid | EelFlags::send_ip_valid | EelFlags::send_port_valid;
-            self.idle_check() && self.flags.contains(valid_send_settings)
+            let file_valid = self.flags.contains(EelFlags::file_valid);
+            let valid_code_settings = file_valid && self.send_code.is_some();
+            let valid_relay_settings = file_valid && self.send_via_relay && self.relay_config().is_some();
+            self.flags.contains(valid_send_settings) || valid_code_settings || valid_relay_settings
         };
 
         ui.horizontal(|ui| {
             ui.vertical(|ui| {
                 ui.label("Target IP:");
-                let ip_textbox = ui.add_enabled(self.idle_check(), TextEdit::singleline(&mut self.send_ip_str));
+                let ip_textbox = ui.add(TextEdit::singleline(&mut self.send_ip_str));
 
                 if ip_textbox.changed() {
-                    // reparse the IP
-                    match Self::check_ip(&self.send_ip_str) {
-                        Ok(ip) => {
-                            self.flags.insert(EelFlags::send_ip_valid);
-                            self.send_ip = Some(ip);
-                        }
-                        Err(_) => {
-                            self.flags.remove(EelFlags::send_ip_valid);
-                            self.send_ip = None;
-                        }
-                    }
+                    self.reparse_send_ip();
                 }
             });
 
@@ -193,30 +434,28 @@ impl UiApp {
 
             ui.vertical(|ui| {
                 ui.label("Port");
-                let send_port_field = ui.add_enabled(self.idle_check(),
-                                                     TextEdit::singleline(&mut self.port_send_str).desired_width(50.0), // Make it narrower
+                let send_port_field = ui.add(
+                    TextEdit::singleline(&mut self.port_send_str).desired_width(50.0), // Make it narrower
                 );
 
-                self.port_send_str.retain(|c| c.is_digit(10));
+                self.port_send_str.retain(|c| c.is_ascii_digit());
 
                 if send_port_field.changed() {
-                    // reparse port
-                    match Self::validate_port(self.port_send_str.as_str()) {
-                        Ok(port) => {
-                            self.flags.insert(EelFlags::send_port_valid);
-                            self.port_send = Some(port);
-                        }
-                        Err(_) => {
-                            self.flags.remove(EelFlags::send_port_valid);
-                            self.port_send = None;
-                        }
-                    }
+                    self.reparse_send_port();
                 }
 
             });
 
             ui.add_space(0.5);
 
+            ui.vertical(|ui| {
+                ui.label("Streams:");
+                ui.add(egui::DragValue::new(&mut self.send_streams).range(1..=MAX_STREAMS))
+                    .on_hover_text("Parallel connections, helps on slow far away links");
+            });
+
+            ui.add_space(0.5);
+
             ui.vertical(|ui| {
                 ui.label("Password:");
                 ui.add_enabled(false, TextEdit::singleline(&mut self.password));
@@ -226,8 +465,235 @@ impl UiApp {
         ui.add_space(0.5);
         
         if ui.add_enabled(send_button_enabled, Button::new("SEND")).clicked() {
-            let socket = SocketAddrV4::new(self.send_ip.unwrap(), self.port_send.unwrap());
-            self.controller.send(socket, self.file_info.clone().unwrap());
+            match self.resolve_send_target() {
+                Some(socket) => {
+                    self.last_peer = Some(socket);
+                    let options = SendOptions {
+                        code: self.send_code.clone(),
+                        relay: self.send_via_relay.then(|| self.relay_config()).flatten(),
+                        streams: self.send_streams,
+                        tuning: Tuning::from_settings(&self.settings),
+                        size_units: self.settings.size_units,
+                    };
+                    self.controller.send(socket, self.file_info.clone().unwrap(), options.clone());
+
+                    for file_info in self.dropped_batch.drain(..) {
+                        self.controller.send(socket, file_info, options.clone());
+                    }
+                }
+                None if self.send_via_relay => {
+                    self.logger.lock().unwrap().warn("The relay address should look like 1.2.3.4:47500.");
+                }
+                None => {
+                    let nameplate = self.send_code.as_ref().unwrap().nameplate;
+                    self.logger.lock().unwrap().warn(&format!("No receiver on the LAN is waiting for code number {}.", nameplate));
+                }
+            }
+        }
+
+        self.draw_save_peer_ui(ui);
+        self.draw_queue_ui(ui);
+    }
+
+    fn draw_queue_ui(&mut self, ui: &mut Ui) {
+        let jobs = self.controller.jobs();
+
+        if jobs.is_empty() {
+            return;
+        }
+
+        egui::CollapsingHeader::new(format!("Outgoing transfers ({})", jobs.len()))
+            .default_open(true)
+            .show(ui, |ui| {
+                ScrollArea::vertical().id_salt("send_queue").max_height(120.0).show(ui, |ui| {
+                    for job in &jobs {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("#{} {} -> {}", job.id, job.file_info.name, job.addr));
+                            ui.add(egui::ProgressBar::new(job.progress).desired_width(100.0).text(job.state.to_string()));
+
+                            if ui.small_button("▲").on_hover_text("Move up").clicked() {
+                                self.controller.move_job(job.id, -1);
+                            }
+
+                            if ui.small_button("▼").on_hover_text("Move down").clicked() {
+                                self.controller.move_job(job.id, 1);
+                            }
+
+                            let pause_label = match &job.state {
+                                JobState::Running(AppState::Paused) => Some(("Resume", false)),
+                                JobState::Running(AppState::Sending) => Some(("Pause", true)),
+                                _ => None,
+                            };
+
+                            if let Some((label, pause)) = pause_label && ui.small_button(label).clicked() {
+                                self.controller.pause_job(job.id, pause);
+                            }
+
+                            if job.state.is_finished() {
+                                if job.state != JobState::Delivered && ui.small_button("Retry").clicked() {
+                                    self.controller.retry_job(job.id);
+                                }
+                            } else if ui.small_button("Cancel").clicked() {
+                                self.controller.cancel_job(job.id);
+                            }
+                        });
+                    }
+                });
+
+                if ui.button("Clear finished").clicked() {
+                    self.controller.clear_finished_jobs();
+                }
+            });
+    }
+
+    fn draw_contacts_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Contact:");
+
+            let mut picked: Option<SocketAddrV4> = None;
+
+            egui::ComboBox::from_id_salt("contact_picker")
+                .selected_text("Pick a saved peer")
+                .width(200.0)
+                .show_ui(ui, |ui| {
+                    for contact in &self.contacts.contacts {
+                        for addr in &contact.addresses {
+                            if ui.selectable_label(false, format!("{} ({})", contact.name, addr)).clicked() {
+                                picked = Some(*addr);
+                            }
+                        }
+                    }
+                });
+
+            if let Some(addr) = picked {
+                self.send_ip_str = addr.ip().to_string();
+                self.port_send_str = addr.port().to_string();
+                self.reparse_send_ip();
+                self.reparse_send_port();
+            }
+
+            if ui.button("Import").clicked() {
+                self.import_contacts();
+            }
+
+            if ui.button("Export").clicked() {
+                self.export_contacts();
+            }
+        });
+    }
+
+    // a code wins over the IP fields, its nameplate is looked up among the announced receivers
+    fn resolve_send_target(&self) -> Option<SocketAddrV4> {
+        if self.send_via_relay {
+            return self.relay_config().map(|relay| relay.addr);
+        }
+
+        match &self.send_code {
+            Some(code) => self
+                .controller
+                .discovered_peers()
+                .iter()
+                .find(|peer| peer.announcement.nameplate == Some(code.nameplate))
+                .map(|peer| peer.addr),
+            None => Some(SocketAddrV4::new(self.send_ip?, self.port_send?)),
+        }
+    }
+
+    fn draw_discovery_ui(&mut self, ui: &mut Ui) {
+        let peers = self.controller.discovered_peers();
+
+        ui.horizontal(|ui| {
+            ui.label("Nearby:");
+
+            let mut picked: Option<SocketAddrV4> = None;
+            let hint = match peers.len() {
+                0 => "No receivers found on the LAN".to_string(),
+                n => format!("{} receiver(s) found", n),
+            };
+
+            ui.add_enabled_ui(!peers.is_empty(), |ui| {
+                egui::ComboBox::from_id_salt("discovery_picker")
+                    .selected_text(hint)
+                    .width(200.0)
+                    .show_ui(ui, |ui| {
+                        for peer in &peers {
+                            if ui.selectable_label(false, format!("{} ({})", peer.announcement.name, peer.addr)).clicked() {
+                                picked = Some(peer.addr);
+                            }
+                        }
+                    });
+            });
+
+            if let Some(addr) = picked {
+                self.send_ip_str = addr.ip().to_string();
+                self.port_send_str = addr.port().to_string();
+                self.reparse_send_ip();
+                self.reparse_send_port();
+            }
+        });
+    }
+
+    // only offered right after a successful send, so we know the peer actually works
+    fn draw_save_peer_ui(&mut self, ui: &mut Ui) {
+        let Some(peer) = self.last_peer else { return };
+
+        let last_send_succeeded = self
+            .controller
+            .jobs()
+            .iter()
+            .rev()
+            .find(|job| job.addr == peer)
+            .is_some_and(|job| job.state == JobState::Delivered);
+
+        if !last_send_succeeded {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Save {} as:", peer));
+            ui.add(TextEdit::singleline(&mut self.new_contact_name).desired_width(120.0));
+
+            if ui.add_enabled(!self.new_contact_name.trim().is_empty(), Button::new("Save this peer")).clicked() {
+                self.contacts.add_or_update(Contact {
+                    name: self.new_contact_name.trim().to_string(),
+                    addresses: vec![peer],
+                    public_key: None,
+                });
+
+                match self.contacts.save() {
+                    Ok(_) => self.logger.lock().unwrap().log(&format!("Saved {} to contacts.", peer)),
+                    Err(e) => self.logger.lock().unwrap().warn(&format!("Could not save contacts: {}", e)),
+                }
+
+                self.new_contact_name.clear();
+                self.last_peer = None;
+            }
+        });
+    }
+
+    fn import_contacts(&mut self) {
+        let Some(path) = FileDialog::new().add_filter("contacts", &["json"]).pick_file() else { return };
+
+        let result = self.contacts.import(&path).and_then(|added| {
+            self.contacts.save()?;
+            Ok(added)
+        });
+
+        match result {
+            Ok(added) => self.logger.lock().unwrap().log(&format!("Imported {} new contact(s).", added)),
+            Err(e) => self.logger.lock().unwrap().warn(&format!("Could not import contacts: {}", e)),
+        }
+    }
+
+    fn export_contacts(&mut self) {
+        let Some(path) = FileDialog::new()
+            .add_filter("contacts", &["json"])
+            .set_file_name("eel_contacts.json")
+            .save_file() else { return };
+
+        match self.contacts.export(&path) {
+            Ok(_) => self.logger.lock().unwrap().log(&format!("Exported contacts to {}", path.display())),
+            Err(e) => self.logger.lock().unwrap().warn(&format!("Could not export contacts: {}", e)),
         }
     }
 
@@ -284,48 +750,284 @@ impl UiApp {
 
             ui.add_space(0.5);
 
+            ui.vertical(|ui| {
+                ui.label("LAN:");
+                ui.add_enabled(self.idle_check(), egui::Checkbox::new(&mut self.announce_listener, "Announce"));
+            });
+
+            ui.vertical(|ui| {
+                ui.label("Router:");
+                ui.add_enabled(self.idle_check(), egui::Checkbox::new(&mut self.map_port, "UPnP"))
+                    .on_hover_text("Ask the router to forward the port while listening");
+            });
+
+            ui.vertical(|ui| {
+                ui.label("One-time:");
+                ui.add_enabled(self.idle_check(), egui::Checkbox::new(&mut self.use_transfer_code, "Use a code"))
+                    .on_hover_text("Senders on this network can find you by the code, anywhere else they also need the relay or your IP");
+            });
+
+            ui.add_space(0.5);
+
             ui.vertical(|ui| {
                 ui.label("Password:");
 
-                ui.add_enabled(
-                    false,
-                    // this is shit and awfully specific, if I weren't lazy, I'd do it with the layout
-                    TextEdit::singleline(&mut self.password).desired_width(213.0),
-                );
+                let width = ui.available_width();
+                ui.add_enabled(false, TextEdit::singleline(&mut self.password).desired_width(width));
             });
         });
 
         ui.add_space(0.5);
         
+        self.draw_relay_ui(ui);
+
         let listen_button_enabled = {
             let valid_listen_settings: EelFlags = EelFlags::receive_port_valid | EelFlags::listen_dir_valid;
-            self.flags.contains(valid_listen_settings) && self.current_state == AppState::Idle
+            let valid_relay_settings = self.flags.contains(EelFlags::listen_dir_valid)
+                && self.listen_via_relay
+                && self.relay_config().is_some();
+            (self.flags.contains(valid_listen_settings) || valid_relay_settings) && self.current_state == AppState::Idle
         };
         
         // todo: validation of reception folder
         if ui.add_enabled(listen_button_enabled, Button::new("LISTEN")).clicked() {
-            self.controller.listen(self.receive_dir_path.clone().unwrap(), self.port_recv.unwrap());
+            self.start_listening();
+        }
+
+        if let Some(code) = &self.active_code {
+            if self.idle_check() {
+                self.active_code = None;
+            } else {
+                ui.horizontal(|ui| {
+                    ui.label("Tell the sender this code:");
+                    ui.label(egui::RichText::new(code.to_string()).monospace().strong());
+
+                    if ui.button("Copy").clicked() {
+                        ui.ctx().copy_text(code.to_string());
+                    }
+                });
+            }
         }
 
     }
 
-    fn draw_status_ui(&mut self, ui: &mut Ui) {
-        let stop_enabled = {
-            let app_state = &self.logger.lock().unwrap().app_state;
-               *app_state != AppState::Idle && *app_state != AppState::Handshake
+    fn start_listening(&mut self) {
+        self.active_code = self.use_transfer_code.then(TransferCode::generate);
+
+        // the sender finds a coded listener through its announcement, so it has to announce
+        let options = ListenOptions {
+            announce: self.announce_listener || self.active_code.is_some(),
+            code: self.active_code.clone(),
+            relay: self.listen_via_relay.then(|| self.relay_config()).flatten(),
+            map_port: self.map_port,
+            tuning: Tuning::from_settings(&self.settings),
+            conflict: self.settings.conflict,
+            size_units: self.settings.size_units,
+        };
+        // going through the relay nothing gets bound here, so it doesn't need a port of ours
+        let Some(port) = self.port_recv.or(options.relay.is_some().then_some(0)) else {
+            return;
         };
+        self.controller.listen(self.receive_dir_path.clone().unwrap(), port, options);
+
+        if self.settings.listen_dir != self.receive_dir_path || self.settings.listen_port != self.port_recv {
+            self.settings.listen_dir = self.receive_dir_path.clone();
+            self.settings.listen_port = self.port_recv;
+            self.settings_draft = self.settings.clone();
+            self.save_settings();
+        }
+    }
+
+    // changes apply as soon as they're valid, transfers that are already running keep what they started with
+    fn draw_settings_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.settings_open;
+
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let draft = &mut self.settings_draft;
+
+                egui::Grid::new("settings_grid").num_columns(2).spacing([12.0, 6.0]).show(ui, |ui| {
+                    ui.label("Buffer size (KiB):");
+                    ui.add(egui::DragValue::new(&mut draft.chunk_size_kib).range(4..=MAX_CHUNK_SIZE_KIB));
+                    ui.end_row();
+
+                    ui.label("Give up after a stall of (s):");
+                    ui.add(egui::DragValue::new(&mut draft.stall_timeout_secs).range(5..=3600));
+                    ui.end_row();
+
+                    ui.label("Keepalive while paused (s):");
+                    ui.add(egui::DragValue::new(&mut draft.keepalive_secs).range(1..=3600));
+                    ui.end_row();
+
+                    ui.label("If the file already exists:");
+                    egui::ComboBox::from_id_salt("conflict_policy")
+                        .selected_text(draft.conflict.to_string())
+                        .show_ui(ui, |ui| {
+                            for policy in [ConflictPolicy::Refuse, ConflictPolicy::Rename, ConflictPolicy::Overwrite] {
+                                ui.selectable_value(&mut draft.conflict, policy, policy.to_string());
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Theme:");
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut draft.theme, Theme::System, "System");
+                        ui.selectable_value(&mut draft.theme, Theme::Light, "Light");
+                        ui.selectable_value(&mut draft.theme, Theme::Dark, "Dark");
+                    });
+                    ui.end_row();
+
+                    ui.label("Sizes:");
+                    egui::ComboBox::from_id_salt("size_units")
+                        .selected_text(draft.size_units.to_string())
+                        .show_ui(ui, |ui| {
+                            for units in [SizeUnits::Binary, SizeUnits::Decimal] {
+                                ui.selectable_value(&mut draft.size_units, units, units.to_string());
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Log:");
+                    egui::ComboBox::from_id_salt("log_level")
+                        .selected_text(draft.log_level.to_string())
+                        .show_ui(ui, |ui| {
+                            for level in [LogLevel::Debug, LogLevel::Info, LogLevel::Warn, LogLevel::Error] {
+                                ui.selectable_value(&mut draft.log_level, level, level.to_string());
+                            }
+                        });
+                    ui.end_row();
+
+                    // for attaching to bug reports
+                    ui.label("Log file:");
+                    ui.label(egui::RichText::new(LogFile::default_path().display().to_string()).small());
+                    ui.end_row();
+
+                    ui.label("On launch:");
+                    ui.checkbox(&mut draft.auto_listen, "Start listening")
+                        .on_hover_text("With the port and folder the listener was last started with");
+                    ui.end_row();
+
+                    ui.label("Closing the window:");
+                    ui.checkbox(&mut draft.run_in_tray, tray::CLOSING_KEEPS_RUNNING)
+                        .on_hover_text("Together with listening on launch the window starts out of the way too");
+                    ui.end_row();
+
+                    ui.label("Notify in the background:");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut draft.notifications.offer, "Offers");
+                        ui.checkbox(&mut draft.notifications.complete, "Finished");
+                        ui.checkbox(&mut draft.notifications.failed, "Failed");
+                    });
+                    ui.end_row();
+
+                    ui.label("Eel fact odds (%):");
+                    ui.horizontal(|ui| {
+                        let odds = &mut draft.fact_odds;
+                        for (label, chance) in [("normal", &mut odds.normal), ("sus", &mut odds.sus), ("amogus", &mut odds.amogus), ("insane", &mut odds.insanity)] {
+                            ui.add(egui::DragValue::new(chance).range(0..=100).suffix(format!(" {}", label)));
+                        }
+                    });
+                    ui.end_row();
+                });
+
+                if let Err(e) = draft.validate() {
+                    ui.label(egui::RichText::new(e.to_string()).color(egui::Color32::from_rgb(200, 10, 20)));
+                }
+
+                if ui.button("Reset to defaults").clicked() {
+                    // where the listener was last started isn't really a setting, that stays
+                    *draft = Settings {
+                        listen_port: draft.listen_port,
+                        listen_dir: draft.listen_dir.clone(),
+                        ..Settings::default()
+                    };
+                }
+            });
+
+        if self.settings_draft != self.settings && self.settings_draft.validate().is_ok() {
+            self.settings = self.settings_draft.clone();
+            Self::apply_theme(ctx, self.settings.theme);
+            self.logger.lock().unwrap().level = self.settings.log_level;
+            self.controller.configure_notifications(self.settings.notifications, self.settings.size_units);
+        }
+
+        // written once the window closes instead of on every drag
+        if self.settings_open && !open {
+            self.save_settings();
+        }
+
+        self.settings_open = open;
+    }
+
+    fn save_settings(&self) {
+        if let Err(e) = self.settings.save(&Settings::default_path()) {
+            self.logger.lock().unwrap().warn(&format!("Could not save the settings: {}", e));
+        }
+    }
+
+    fn draw_relay_ui(&mut self, ui: &mut Ui) {
+        egui::CollapsingHeader::new("Relay server (for when nobody can forward ports)").show(ui, |ui| {
+            ui.add_enabled_ui(self.idle_check(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Relay:");
+                    ui.add(
+                        TextEdit::singleline(&mut self.relay_addr_str)
+                            .hint_text(format!("1.2.3.4:{}", DEFAULT_RELAY_PORT))
+                            .desired_width(140.0),
+                    );
+                    ui.label("Session:");
+                    ui.add(TextEdit::singleline(&mut self.relay_session_str).desired_width(100.0));
+                });
 
-        // ui.heading("Status");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.send_via_relay, "Send via relay");
+                    ui.checkbox(&mut self.listen_via_relay, "Listen via relay");
+                });
+            });
+        });
+    }
+
+    fn relay_config(&self) -> Option<RelayConfig> {
+        let session = self.relay_session_str.trim();
+
+        if session.is_empty() || session.contains(' ') {
+            return None;
+        }
 
+        Some(RelayConfig {
+            addr: self.relay_addr_str.trim().parse().ok()?,
+            session: session.to_string(),
+        })
+    }
+
+    // receiving and sending side by side, each with its own animation and progress, the log under both
+    fn draw_status_ui(&mut self, ui: &mut Ui) {
+        ui.columns(2, |columns| {
+            self.draw_receiver_status_ui(&mut columns[0]);
+            self.draw_sender_status_ui(&mut columns[1]);
+        });
+
+        self.log_view.show(ui, &self.logger);
+    }
+
+    fn draw_receiver_status_ui(&mut self, ui: &mut Ui) {
+        let status = self.logger.lock().unwrap().receiver.clone();
+        let stop_enabled = status.state != AppState::Idle && status.state != AppState::Handshake;
+        let pause_enabled = matches!(status.state, AppState::Accepting | AppState::Paused);
+
+        ui.label("Receiving");
         ui.add(
-            egui::Image::new(self.animation_manager.get_image_source(self.logger.lock().unwrap().animation.clone()))
+            egui::Image::new(self.receive_animation.get_image_source(status.animation))
                 .fit_to_original_size(1.0)
         );
 
         self.reparse_status_message();
-        ui.label(format!("{}", self.status_message));
+        ui.label(&self.status_message);
 
-        self.progress = self.logger.lock().unwrap().progress;
+        self.progress = status.progress;
 
         ui.add(egui::ProgressBar::new(self.progress));
 
@@ -335,29 +1037,46 @@ impl UiApp {
             if ui.add_enabled(stop_enabled, Button::new("ABORT")).clicked() {
                 self.controller.abort();
             }
+
+            // the other side pausing shows up as Paused too, RESUME then only lifts our own pause
+            if status.state == AppState::Paused {
+                if ui.add_enabled(pause_enabled, Button::new("RESUME")).clicked() {
+                    self.controller.resume();
+                }
+            } else if ui.add_enabled(pause_enabled, Button::new("PAUSE")).clicked() {
+                self.controller.pause();
+            }
+        });
+    }
+
+    // the queue rolled into one, the per-job details and buttons are in the list above
+    fn draw_sender_status_ui(&mut self, ui: &mut Ui) {
+        let status = self.logger.lock().unwrap().sender.clone();
+        let jobs = self.controller.jobs();
+        let running = jobs.iter().filter(|job| matches!(job.state, JobState::Running(_))).count();
+        let queued = jobs.iter().filter(|job| job.state == JobState::Queued).count();
+
+        ui.label("Sending");
+        ui.add(
+            egui::Image::new(self.send_animation.get_image_source(status.animation))
+                .fit_to_original_size(1.0)
+        );
+
+        ui.label(format!("{} running, {} queued", running, queued));
+        ui.add(egui::ProgressBar::new(status.progress));
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Progress: {}%", (status.progress * 100.0).round()));
+
+            if ui.add_enabled(running + queued > 0, Button::new("CANCEL ALL")).clicked() {
+                self.controller.cancel_all_jobs();
+            }
         });
-        
-        let mut log_text = self.logger.lock().unwrap().messages.clone();
-        ScrollArea::vertical()
-            .auto_shrink([false; 2])
-            .stick_to_bottom(true)
-            .show(ui, |ui| {
-                ui.add(
-                    TextEdit::multiline(&mut log_text)
-                        .font(egui::TextStyle::Monospace)
-                        .desired_rows(10)
-                        .desired_width(f32::INFINITY)
-                        .interactive(false)
-                );
-            });
     }
     
+    // only the receiving side's settings are locked while it's busy, sends just get queued
     fn idle_check(&self) -> bool {
-        if let AppState::Idle = self.logger.lock().unwrap().app_state {
-            true
-        } else {
-            false
-        }
+        matches!(self.logger.lock().unwrap().receiver.state, AppState::Idle)
     }
 
     fn validate_port(port: &str) -> Result<u16, ParseIntError> {
@@ -367,6 +1086,69 @@ impl UiApp {
         }
     }
 
+    // checks the file can be read and fills in what gets offered to the receiver, folders can't be sent
+    fn select_file(&mut self, path: PathBuf) -> bool {
+        self.selected_file_path = Some(path.clone());
+        self.file_info = Self::read_metadata(path);
+        self.flags.set(EelFlags::file_valid, self.file_info.is_some());
+        self.file_info.is_some()
+    }
+
+    // None for anything that can't be opened as a plain file
+    fn read_metadata(path: PathBuf) -> Option<FileInfo> {
+        let file = File::open(&path).ok().filter(|file| file.metadata().is_ok_and(|metadata| metadata.is_file()))?;
+        Some(UiApp::generate_metadata(&file, path))
+    }
+
+    // the first file goes into the file field and the rest wait next to it, folders are left out
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped: Vec<PathBuf> = ctx.input(|i| i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect());
+        let (files, folders): (Vec<PathBuf>, Vec<PathBuf>) = dropped.into_iter().partition(|path| !path.is_dir());
+
+        for folder in &folders {
+            self.logger.lock().unwrap().warn(&format!("Can't send {}, folders aren't supported. Drop the files in it instead.", folder.display()));
+        }
+
+        let mut readable = Vec::new();
+
+        for path in files {
+            match Self::read_metadata(path.clone()) {
+                Some(file_info) => readable.push((path, file_info)),
+                None => self.logger.lock().unwrap().warn(&format!("Can't send {}, it can't be read.", path.display())),
+            }
+        }
+
+        let mut readable = readable.into_iter();
+        let Some((path, _)) = readable.next() else { return };
+
+        self.selected_file_str = path.to_string_lossy().into_owned();
+        self.select_file(path);
+        self.dropped_batch = readable.map(|(_, file_info)| file_info).collect();
+
+        if !self.dropped_batch.is_empty() {
+            self.logger.lock().unwrap().log(&format!("{} more file(s) will be queued along with it on SEND.", self.dropped_batch.len()));
+        }
+    }
+
+    fn draw_drop_overlay(ctx: &egui::Context) {
+        if ctx.input(|i| i.raw.hovered_files.is_empty()) {
+            return;
+        }
+
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("drop_overlay")));
+        let screen = ctx.screen_rect();
+
+        painter.rect_filled(screen, 0.0, egui::Color32::from_black_alpha(192));
+        painter.rect_stroke(screen.shrink(8.0), 8.0, egui::Stroke::new(3.0, egui::Color32::LIGHT_GREEN), egui::StrokeKind::Inside);
+        painter.text(
+            screen.center(),
+            egui::Align2::CENTER_CENTER,
+            "Drop files here to send them (not folders)",
+            egui::FontId::proportional(24.0),
+            egui::Color32::WHITE,
+        );
+    }
+
     fn generate_metadata(file: &File, path: PathBuf) -> FileInfo {
         // name, extension, size, hash
         let metadata = file.metadata().unwrap();
@@ -380,31 +1162,58 @@ impl UiApp {
             size,
             name,
             sender_addr: None,
+            compression: Vec::new(),
+            streams: 1,
+        }
+    }
+
+    fn check_ip(ip: &str) -> Result<Ipv4Addr, AddrParseError> {
+        ip.parse::<Ipv4Addr>()
+    }
+
+    fn reparse_send_ip(&mut self) {
+        match Self::check_ip(&self.send_ip_str) {
+            Ok(ip) => {
+                self.flags.insert(EelFlags::send_ip_valid);
+                self.send_ip = Some(ip);
+            }
+            Err(_) => {
+                self.flags.remove(EelFlags::send_ip_valid);
+                self.send_ip = None;
+            }
         }
     }
 
-    fn check_ip(ip: &String) -> Result<Ipv4Addr, AddrParseError> {
-        Ok(ip.parse::<Ipv4Addr>()?)
+    fn reparse_send_port(&mut self) {
+        match Self::validate_port(self.port_send_str.as_str()) {
+            Ok(port) => {
+                self.flags.insert(EelFlags::send_port_valid);
+                self.port_send = Some(port);
+            }
+            Err(_) => {
+                self.flags.remove(EelFlags::send_port_valid);
+                self.port_send = None;
+            }
+        }
     }
 
     fn validate_listen_dir(&mut self) {
         match &self.receive_dir_path {
             None => { self.flags.remove(EelFlags::listen_dir_valid); },
-            Some(path) => {
-                if path.is_dir() {
-                    let test_file = path.join(".permission_test");
-                    match OpenOptions::new().write(true).create_new(true).open(&test_file) {
-                        Ok(_) => {
-                            // Clean up the test file immediately
-                            let _ = remove_file(test_file);
-                            self.flags.insert(EelFlags::listen_dir_valid);
-                        }
-                        Err(_) => self.flags.remove(EelFlags::listen_dir_valid),
+            Some(path) if path.is_dir() => {
+                let test_file = path.join(".permission_test");
+                match OpenOptions::new().write(true).create_new(true).open(&test_file) {
+                    Ok(_) => {
+                        // Clean up the test file immediately
+                        let _ = remove_file(test_file);
+                        self.flags.insert(EelFlags::listen_dir_valid);
                     }
-                } else {
-                    self.flags.remove(EelFlags::listen_dir_valid);
+                    Err(_) => self.flags.remove(EelFlags::listen_dir_valid),
                 }
             }
+            Some(_) => {
+                self.flags.remove(EelFlags::listen_dir_valid);
+            }
         }
     }
 
@@ -412,21 +1221,9 @@ impl UiApp {
 
     fn reparse_status_message(&mut self) {
         // bad
-        if (self.current_state == AppState::Accepting || self.current_state == AppState::Sending)
-            && (self.prev_state != AppState::Accepting || self.prev_state != AppState::Sending) {
-
-            match self.current_state {
-                AppState::Accepting => {
-                    let metadata = self.logger.lock().unwrap().metadata.clone().unwrap();
-                    self.status_message = format!("Accepting file: {}, size: {}", metadata.name, Util::display_size(metadata.size));
-                }
-
-                AppState::Sending => {
-                    let file_info_ref = self.file_info.as_ref().unwrap();
-                    self.status_message = format!("Sending file: {}, size: {}", file_info_ref.name, Util::display_size(file_info_ref.size));
-                }
-                _ => {}
-            }
+        if self.current_state == AppState::Accepting && self.prev_state != AppState::Accepting {
+            let metadata = self.logger.lock().unwrap().metadata.clone().unwrap();
+            self.status_message = format!("Accepting file: {}, size: {}", metadata.name, Util::format_size(metadata.size, self.settings.size_units));
         }
     }
 
@@ -540,6 +1337,9 @@ impl AnimationManager {
             Animation::ConnectingStatic => {
                 Self::get_animation_source("connecting_static", CONNECTING_STATIC)
             }
+            Animation::Paused => {
+                Self::get_animation_source("paused", PAUSED)
+            }
         }
     }
 }
\ No newline at end of file
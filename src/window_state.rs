@@ -0,0 +1,42 @@
+use crate::{EelError, Util};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_SIZE: [f32; 2] = [600.0, 530.0];
+// below this the send and receive forms stop being usable
+pub const MIN_SIZE: [f32; 2] = [480.0, 420.0];
+
+// how big the window was and where it was when the app last closed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct WindowState {
+    pub size: [f32; 2],
+    // not every window manager tells us, it picks a spot itself then
+    pub position: Option<[f32; 2]>,
+}
+
+impl Default for WindowState {
+    fn default() -> Self {
+        WindowState {
+            size: DEFAULT_SIZE,
+            position: None,
+        }
+    }
+}
+
+impl WindowState {
+    pub fn default_path() -> PathBuf {
+        Util::app_data_dir().join("window.json")
+    }
+
+    // a missing file just means the app hasn't been closed properly yet
+    pub fn load(path: &Path) -> Result<WindowState, EelError> {
+        let mut state: WindowState = Util::load_json(path, EelError::ConfigError)?;
+
+        state.size = [state.size[0].max(MIN_SIZE[0]), state.size[1].max(MIN_SIZE[1])];
+        Ok(state)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), EelError> {
+        Util::write_json(path, self, EelError::ConfigError)
+    }
+}
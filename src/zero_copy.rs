@@ -0,0 +1,187 @@
+// Linux only: raw frame payloads go file -> socket with sendfile and socket -> pipe -> file with splice,
+// so the bytes never get copied through our own buffers. Frame headers still go through tokio as usual,
+// which is fine because TcpStream doesn't buffer anything on our side.
+// The file ends of both calls are plain blocking disk I/O, so those run on the blocking pool like tokio::fs does,
+// with their own copies of the fds in case the transfer gets dropped while the call is still going.
+// A sendfile dropped like that still puts its bytes on the wire, so an abandoned range shuts the socket down behind it.
+use std::io::{Error, ErrorKind};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use tokio::io::Interest;
+use tokio::net::TcpStream;
+
+// the kernel default is 64KiB, bigger means fewer round trips through the pipe per frame
+const PIPE_SIZE: libc::c_int = 1024 * 1024;
+
+pub async fn send_file_range(stream: &TcpStream, file: &impl AsFd, offset: u64, len: usize) -> Result<(), Error> {
+    let mut offset = offset as libc::off_t;
+    let mut remaining = len;
+    let unfinished = Unfinished(stream.as_fd());
+
+    while remaining > 0 {
+        stream.writable().await?;
+
+        let (socket, file) = (stream.as_fd().try_clone_to_owned()?, file.as_fd().try_clone_to_owned()?);
+        // the socket stays non-blocking, so this only ever waits on the disk
+        let (result, advanced) = blocking(move || {
+            // sendfile advances `offset` itself and leaves the file position alone
+            let sent = unsafe { libc::sendfile(socket.as_raw_fd(), file.as_raw_fd(), &mut offset, remaining) };
+            (if sent < 0 { Err(Error::last_os_error()) } else { Ok(sent as usize) }, offset)
+        })
+        .await?;
+        offset = advanced;
+
+        let sent = match result {
+            Ok(sent) => sent,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                // the socket buffer filled up in the meantime, wait until tokio sees it drain again
+                let _ = stream.try_io(Interest::WRITABLE, || Err::<(), _>(e));
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        if sent == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "the file got shorter while sending it"));
+        }
+
+        remaining -= sent;
+    }
+
+    std::mem::forget(unfinished);
+    Ok(())
+}
+
+// Only dropped when send_file_range gives up or is dropped before the whole range is out. Nobody can tell how much
+// of the frame a sendfile still going on the blocking pool gets out, anything written on the connection after it
+// would land in the middle of it, so nothing can be.
+struct Unfinished<'a>(BorrowedFd<'a>);
+
+impl Drop for Unfinished<'_> {
+    fn drop(&mut self) {
+        unsafe { libc::shutdown(self.0.as_raw_fd(), libc::SHUT_RDWR) };
+    }
+}
+
+pub struct Pipe {
+    read: OwnedFd,
+    write: OwnedFd,
+}
+
+impl Pipe {
+    pub fn new() -> Result<Pipe, Error> {
+        let mut fds = [0 as libc::c_int; 2];
+
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let pipe = unsafe {
+            Pipe {
+                read: OwnedFd::from_raw_fd(fds[0]),
+                write: OwnedFd::from_raw_fd(fds[1]),
+            }
+        };
+
+        // not being allowed a bigger pipe (pipe-max-size) only makes it slower
+        unsafe { libc::fcntl(pipe.write.as_raw_fd(), libc::F_SETPIPE_SZ, PIPE_SIZE) };
+
+        Ok(pipe)
+    }
+
+    // moves exactly `len` bytes off the socket into the file at `offset`
+    pub async fn splice_to_file(&self, stream: &TcpStream, file: &impl AsFd, offset: u64, len: usize) -> Result<(), Error> {
+        let mut offset = offset as libc::loff_t;
+        let mut remaining = len;
+
+        while remaining > 0 {
+            let in_pipe = stream
+                .async_io(Interest::READABLE, || {
+                    let moved = unsafe {
+                        libc::splice(
+                            stream.as_raw_fd(),
+                            std::ptr::null_mut(),
+                            self.write.as_raw_fd(),
+                            std::ptr::null_mut(),
+                            remaining,
+                            libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+                        )
+                    };
+                    if moved < 0 { Err(Error::last_os_error()) } else { Ok(moved as usize) }
+                })
+                .await?;
+
+            if in_pipe == 0 {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "the sender hung up in the middle of a frame"));
+            }
+
+            let (pipe, file) = (self.read.try_clone()?, file.as_fd().try_clone_to_owned()?);
+            offset = blocking(move || Self::drain_into(&pipe, &file, offset, in_pipe)).await??;
+
+            remaining -= in_pipe;
+        }
+
+        Ok(())
+    }
+
+    // empties `len` bytes out of the pipe into the file, returns where the next write goes
+    fn drain_into(pipe: &OwnedFd, file: &OwnedFd, mut offset: libc::loff_t, len: usize) -> Result<libc::loff_t, Error> {
+        let mut to_drain = len;
+
+        while to_drain > 0 {
+            let written = unsafe {
+                libc::splice(
+                    pipe.as_raw_fd(),
+                    std::ptr::null_mut(),
+                    file.as_raw_fd(),
+                    &mut offset,
+                    to_drain,
+                    libc::SPLICE_F_MOVE,
+                )
+            };
+
+            if written < 0 {
+                return Err(Error::last_os_error());
+            }
+
+            if written == 0 {
+                return Err(Error::new(ErrorKind::WriteZero, "could not write the file"));
+            }
+
+            to_drain -= written as usize;
+        }
+
+        Ok(offset)
+    }
+}
+
+async fn blocking<T: Send + 'static>(call: impl FnOnce() -> T + Send + 'static) -> Result<T, Error> {
+    tokio::task::spawn_blocking(call).await.map_err(Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_abandoned_send_closes_the_connection() {
+        let path = std::env::temp_dir().join(format!("eel_abandoned_{}", rand::random::<u64>()));
+        // more than fits in the socket buffers, and nobody reads it, so it can't be done by the time it's dropped
+        std::fs::write(&path, vec![7u8; 32 << 20]).unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (stream, accepted) = tokio::join!(TcpStream::connect(listener.local_addr().unwrap()), listener.accept());
+        let (mut stream, mut peer) = (stream.unwrap(), accepted.unwrap().0);
+
+        let sending = tokio::time::timeout(std::time::Duration::ZERO, send_file_range(&stream, &file, 0, 32 << 20)).await;
+        assert!(sending.is_err());
+
+        // whatever made it out, the peer sees the end of it instead of a frame that never finishes
+        let ended = tokio::time::timeout(std::time::Duration::from_secs(5), peer.read_to_end(&mut Vec::new())).await;
+        assert!(ended.is_ok(), "the connection was left open");
+        assert!(stream.write_all(b"cancel").await.is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}